@@ -1,4 +1,4 @@
-use std::cmp::{max, min};
+use std::cmp::max;
 use super::super::*;
 use super::super::game::entities::*;
 use super::super::game::bitboard::*;
@@ -27,6 +27,11 @@ static PIECE_VALUES_FOR_CONTROL: [i32; 6] = [
     1, 5, 3, 3, 9, 10
 ];
 
+/// Increasing `PIECE_VALUES_FOR_CONTROL` order
+static PIECES_BY_CONTROL_VALUE: [Piece; 6] = [
+    Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King
+];
+
 static PAWN_Y_CONSTANTS: [(i32, i32); 2] = [(6, -1), (-1, 1)];
 
 // TODO Array
@@ -59,23 +64,17 @@ fn evaluate_player(board: &Board, player: Player) -> i32 {
     let mut value: i32 = 0;
 
     // Count material
-    let mut piece_locs_copy = ps.piece_locs;
-    piece_locs_copy.consume_loop_indices(|index| {
-        if let Square::Occupied(piece, _) = board.get_by_index(index) {
-            value += evaluate_piece(*piece);
-        }
-    });
+    for (piece_num, locs) in ps.piece_type_locs.iter().enumerate() {
+        value += locs.pop_count() as i32 * PIECE_VALUES[piece_num];
+    }
 
     // Reward pawn push in later stages of game
     if value <= MIN_MATERIAL_FOR_PAWN_EVAL {
         let pawn_y_consts = PAWN_Y_CONSTANTS[player as usize];
-        let mut piece_locs_copy = ps.piece_locs;
-        piece_locs_copy.consume_loop_indices(|index| {
+        let mut pawn_locs = board.get_piece_locs(player, Piece::Pawn);
+        pawn_locs.consume_loop_indices(|index| {
             let coord = FastCoord(index).to_coord();
-            if let Square::Occupied(piece, _) = board.get_by_index(index) {
-                let is_pawn_mask = -((*piece == Piece::Pawn) as i32);
-                value += is_pawn_mask & ((pawn_y_consts.0 + pawn_y_consts.1 * (coord.1 as i32)) * PAWN_PUSH_BONUS);
-            }
+            value += (pawn_y_consts.0 + pawn_y_consts.1 * (coord.1 as i32)) * PAWN_PUSH_BONUS;
         });
     }
 
@@ -83,6 +82,17 @@ fn evaluate_player(board: &Board, player: Player) -> i32 {
     value * player.multiplier()
 }
 
+/// `PIECE_VALUE_BOUND_FOR_CONTROL` if none of `attackers` are `player`'s
+#[inline]
+fn get_lowest_attacker_worth(board: &Board, player: Player, attackers: &Bitboard) -> i32 {
+    for piece in PIECES_BY_CONTROL_VALUE.iter() {
+        if attackers.0 & board.get_piece_locs(player, *piece).0 != 0 {
+            return evaluate_piece_for_control(*piece);
+        }
+    }
+    PIECE_VALUE_BOUND_FOR_CONTROL
+}
+
 fn calculate_control(board: &Board, prepared_af_boards: &mut AttackFromBoards) -> i32 {
 
     board.rewrite_af_boards(prepared_af_boards);
@@ -91,19 +101,10 @@ fn calculate_control(board: &Board, prepared_af_boards: &mut AttackFromBoards) -
     for y in 0..8 {
         for x in 0..8 {
             let b = prepared_af_boards.data[y * 8 + x];
-            let mut lowest_attacker_worth: [i32; 2] = [PIECE_VALUE_BOUND_FOR_CONTROL, PIECE_VALUE_BOUND_FOR_CONTROL];
-
-            let mut b2 = b;
-            b2.consume_loop_indices(|index| {
-                match board.get_by_index(index) {
-                    Square::Occupied(attacking_piece, attacking_player) => {
-                        let value = evaluate_piece_for_control(*attacking_piece);
-                        let ref mut lowest_ref = lowest_attacker_worth[*attacking_player as usize];
-                        *lowest_ref = min(*lowest_ref, value);
-                    },
-                    Square::Blank => panic!("Unexpected empty square when attacker is expected")
-                };
-            });
+            let lowest_attacker_worth: [i32; 2] = [
+                get_lowest_attacker_worth(board, Player::White, &b),
+                get_lowest_attacker_worth(board, Player::Black, &b)
+            ];
 
             let one_or_neg_one_or_zero = (lowest_attacker_worth[1] - lowest_attacker_worth[0]).signum();
             let zero_if_white = (one_or_neg_one_or_zero != 1) as i32;
@@ -118,6 +119,10 @@ fn calculate_control(board: &Board, prepared_af_boards: &mut AttackFromBoards) -
 }
 
 pub fn evaluate(board: &Board, prepared_af_boards: &mut AttackFromBoards) -> i32 {
+    if board.is_insufficient_material() {
+        return 0;
+    }
+
     let white_eval = evaluate_player(board, Player::White);
     let black_eval = evaluate_player(board, Player::Black);
     
@@ -132,11 +137,16 @@ pub fn add_captures_to_evals(
 ) {
     m.write_evals(start, end_exclusive, |m| {
         let mut score = m.eval();
-        if let MoveDescription::NormalMove(_from_coord, _to_coord) = m.description() {
+        if let MoveDescription::Promotion(_, _, promote_to) = m.description() {
+            score += evaluate_piece(*promote_to) - evaluate_piece(Piece::Pawn);
+        }
+        if let MoveDescription::NormalMove(_from_coord, _to_coord) | MoveDescription::Promotion(_from_coord, _to_coord, _) = m.description() {
             if let Square::Occupied(curr_dest_piece, _) = board.get_by_index(_to_coord.value()) {
                 if let Square::Occupied(dragged_piece, _) = board.get_by_index(_from_coord.value()) {
                     score += max(evaluate_piece(*curr_dest_piece) - evaluate_piece(*dragged_piece), MOVE_ORDER_CAPTURE_MIN_VAL);
                 }
+            } else if board.is_en_passant_capture(m) {
+                score += MOVE_ORDER_CAPTURE_MIN_VAL;
             }
         }
         score
@@ -155,8 +165,9 @@ pub fn add_mobility_to_evals(
     m.write_evals(start, end_exclusive, |m| {
         let mut score = m.eval();
 
-        if let MoveDescription::NormalMove(_from_coord, _to_coord) = m.description() {
-            if let Square::Occupied(src_piece, src_player) = board.get_by_index(_from_coord.value()) {
+        if let MoveDescription::NormalMove(_from_coord, _to_coord) | MoveDescription::Promotion(_from_coord, _to_coord, _) = m.description() {
+            if let Square::Occupied(_src_piece, src_player) = board.get_by_index(_from_coord.value()) {
+                let src_piece = if let MoveDescription::Promotion(_, _, promote_to) = m.description() { promote_to } else { _src_piece };
                 let atks = board.get_imaginary_pseudo_move_at(*_to_coord, *src_piece, *src_player);
                 score += atks.pop_count() as i32 * MOVE_ORDER_MOB_SQ_VAL;
 
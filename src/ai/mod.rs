@@ -1,114 +1,292 @@
 mod evaluation;
+pub mod opening_book;
+pub mod transposition_table;
 
 use std::cmp::min;
-use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
 use super::game::entities::*;
 use super::game::move_test::*;
 use super::game::move_list::*;
 use super::game::board::*;
 use super::extern_funcs::now;
+use opening_book::*;
+use transposition_table::*;
 use crate::{console_log};
 
+/// When to stop searching, whichever limit is hit first. The search may run a little past the time and node limits
+pub struct SearchLimits {
+    /// Iterative deepening goes through odd depths up to this one
+    pub depth: i8,
+    pub ms_till_terminate: u128,
+    pub max_nodes: u64,
+    /// Set from another thread to stop the search, eg. by the UCI `stop` command
+    pub stop: Option<Arc<AtomicBool>>
+}
+
+impl SearchLimits {
+    pub fn new(depth: i8, ms_till_terminate: u128) -> Self {
+        Self { depth, ms_till_terminate, max_nodes: u64::MAX, stop: None }
+    }
+}
+
+/// Clock time kept spare for communication delays
+#[cfg(not(target_arch = "wasm32"))]
+static MOVE_OVERHEAD_MS: u64 = 50;
+
+/// Time to spend on one move with `time_left_ms` on the clock, which has to last for `moves_to_go` moves
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_move_time_ms(time_left_ms: u64, increment_ms: u64, moves_to_go: u64) -> u64 {
+    let budget = time_left_ms / moves_to_go.max(1) + increment_ms / 2;
+    budget.min(time_left_ms.saturating_sub(MOVE_OVERHEAD_MS)).max(1)
+}
+
+/// Iterative deepening only searches odd depths, so an even depth limit is rounded up instead of stopping a depth short
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_searched_depth(depth: i8) -> i8 {
+    depth | 1
+}
+
+/// Progress after each fully searched depth
+pub struct SearchInfo<'a> {
+    pub depth: i8,
+    /// Centipawns, from the point of view of the player with the turn
+    pub score: i32,
+    /// Searched by all threads
+    pub nodes: u64,
+    pub best_move: &'a MoveWithEval,
+    /// Permille of the transposition table used by this search
+    pub hashfull: u32
+}
+
 pub struct Ai {
     moves_buf: MoveList,
     test_board: Board,
-    temp_moves: MoveList,
     af_boards: AttackFromBoards,
-    memo: HashMap<u64, MemoData>,
+    /// Shared with helper search threads
+    memo: Arc<TranspositionTable>,
     memo_hits: usize,
     fast_found_hits: usize,
+    /// Searched by this thread
     node_counter: u64,
+    /// Searched by all threads of the current search, which add their nodes in batches of `SEARCH_NODES_BATCH_LEN`
+    search_nodes: Arc<AtomicU64>,
     start_ms: u128,
-    ms_till_terminate: u128,
-    terminated: bool
+    limits: SearchLimits,
+    terminated: bool,
+    /// Best move at the root from the latest search result there, with its score and depth. The memo may replace the root entry
+    leading_move: Option<(MoveWithEval, i32, i8)>,
+    /// Length of the test board hash history at the root of the search
+    root_history_len: usize,
+    book: Option<OpeningBook>,
+    /// Search threads including this one. Wasm has no threads
+    #[cfg(not(target_arch = "wasm32"))]
+    threads: usize
 }
 
 enum SingleMoveResult { NewAlpha(i32), BetaCutOff(i32), NoEffect }
 
-#[derive(Clone)]
-enum MemoType { Low(MoveWithEval), Exact(MoveWithEval), High(MoveWithEval) }
-
-// TODO Evaluation is redundant
-#[derive(Clone)]
-struct MemoData(i32, i8, MemoType);
+/// Moves mate scores `plies` closer to the mate, other scores are unchanged
+fn adjust_mate_eval(score: i32, plies: i32) -> i32 {
+    if score > MATE_EVAL_BOUND {
+        score - plies
+    } else if score < -MATE_EVAL_BOUND {
+        score + plies
+    } else {
+        score
+    }
+}
 
 static MAX_EVAL: i32 = 999999;
+/// Threads only add to the shared node count this often, so they don't all write to it at every node
+static SEARCH_NODES_BATCH_LEN: u64 = 1024;
+/// Checkmates score `MAX_EVAL` minus the plies from the root to the mate, so nearer mates are preferred. Scores beyond this are mates
+static MATE_EVAL_BOUND: i32 = MAX_EVAL - 1000;
+
+/// Signed number of moves to checkmate if `score` is a mate score, negative if the player with the turn is getting mated
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_mate_in(score: i32) -> Option<i32> {
+    if score.abs() > MATE_EVAL_BOUND {
+        let plies = MAX_EVAL - score.abs();
+        Some(score.signum() * (plies + 1) / 2)
+    } else {
+        None
+    }
+}
+
+/// For threads which search, since the search recurses deeper than the default thread stack allows at high depths
+#[cfg(not(target_arch = "wasm32"))]
+pub static SEARCH_STACK_SIZE: usize = 64 * 1024 * 1024;
 
 impl Ai {
 
     pub fn new() -> Self {
         console_log!("AI init");
+        Self::new_with_memo(Arc::new(TranspositionTable::new(DEFAULT_SIZE_MB)))
+    }
+
+    fn new_with_memo(memo: Arc<TranspositionTable>) -> Self {
         Self {
             moves_buf: MoveList::new(1000),
             test_board: Board::new(),
-            temp_moves: MoveList::new(50),
             af_boards: AttackFromBoards::new(),
-            memo: HashMap::new(),
+            memo,
             memo_hits: 0,
             fast_found_hits: 0,
             node_counter: 0,
+            search_nodes: Arc::new(AtomicU64::new(0)),
             start_ms: 0,
-            ms_till_terminate: 5000,
-            terminated: false
+            limits: SearchLimits::new(1, 5000),
+            terminated: false,
+            leading_move: None,
+            root_history_len: 0,
+            book: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            threads: 1
         }
     }
 
-    fn get_leading_move(&self) -> Option<(&MoveWithEval, i8)> {
-        match self.memo.get(&self.test_board.get_hash()) {
-            // In this context, fail high means checkmate
-            Some(MemoData(eval, depth, MemoType::High(best_move) | MemoType::Exact(best_move) | MemoType::Low(best_move))) => {
-                Some((best_move, *depth))
-            },
-            _ => {
-                None
-            }
-        }
+    /// Replaces the transposition table with an empty one of about `size_mb`
+    pub fn set_memo_size_mb(&mut self, size_mb: usize) {
+        self.memo = Arc::new(TranspositionTable::new(size_mb));
     }
 
-    pub fn make_move(&mut self, depth: i8, ms_till_terminate: u128, real_board: &mut Board) {
+    /// Searches with `threads` threads from the next search on, see `iterative_deepening_with_helpers`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
 
-        self.test_board.clone_from(real_board);
+    /// Forgets results of earlier searches, eg. for a new game
+    pub fn clear_memo(&mut self) {
+        self.memo.clear();
+    }
 
-        self.start_ms = now();
-        self.ms_till_terminate = ms_till_terminate;
-        self.terminated = false;
-        for d in (1i8..=depth).step_by(2) {
-            console_log!("\nBegin depth {}", d);
-            unsafe {
-                self.negamax(d, -MAX_EVAL, MAX_EVAL, 0);
-            }
+    /// Book moves are played without searching while the game is in the book
+    pub fn set_book(&mut self, book: Option<OpeningBook>) {
+        self.book = book;
+    }
 
-            let leading_move = self.get_leading_move();
-            if let Some((m, depth)) = leading_move {
-                console_log!("{}, d={}", self.test_board.stringify_move(m), depth);
-            } else {
-                console_log!("No leading move");
-            }
+    /// Best move found for the player with the turn, None if there are no legal moves. `board` is left unchanged
+    pub fn find_move(&mut self, limits: SearchLimits, board: &Board) -> Option<MoveWithEval> {
+        self.find_move_with_info(limits, board, |_| {})
+    }
+
+    /// Same as `find_move`, calling `on_depth` each time a depth is fully searched
+    pub fn find_move_with_info(&mut self, limits: SearchLimits, board: &Board, mut on_depth: impl FnMut(&SearchInfo)) -> Option<MoveWithEval> {
 
-            if self.terminated { 
-                console_log!("Terminated due to time");
-                break; 
+        if let Some(m) = self.book.as_ref().and_then(|book| book.choose_move(board)) {
+            console_log!("Book move: {}", board.stringify_move(&m));
+            return Some(m);
+        }
+
+        self.memo.new_search();
+        self.search_nodes.store(0, Ordering::Relaxed);
+        self.start_search(board, limits);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if self.threads > 1 {
+                self.iterative_deepening_with_helpers(board, &mut on_depth);
+            } else {
+                self.iterative_deepening(1, Some(&mut on_depth));
             }
         }
+        #[cfg(target_arch = "wasm32")]
+        self.iterative_deepening(1, Some(&mut on_depth));
 
         self.test_board.assert_hash();
         self.assert_king_pos(Player::White);
         self.assert_king_pos(Player::Black);
 
-        let leading_move = self.get_leading_move();
-        if let Some((m, e)) = leading_move {
-            console_log!("Making move: {} ({})", self.test_board.stringify_move(m), e);
-            real_board.handle_move(m);
-        } else {
+        let leading_move = self.leading_move.clone().map(|(m, e, _)| {
+            console_log!("Best move: {} ({})", self.test_board.stringify_move(&m), e);
+            m
+        });
+        // Stopped before the first depth finished, any legal move is better than none
+        let leading_move = leading_move.or_else(|| {
+            let mut moves = MoveList::new(100);
+            self.test_board.get_moves(&mut moves);
+            moves.v()[0..moves.write_index].first().cloned()
+        });
+        if leading_move.is_none() {
             console_log!("No move");
         }
-        console_log!("Memo hits - {}, size - {}, fast found - {}", self.memo_hits, self.memo.len(), self.fast_found_hits);
+        console_log!("Memo hits - {}, hashfull - {}, fast found - {}", self.memo_hits, self.memo.get_hashfull(), self.fast_found_hits);
         console_log!("Nodes - {}, NPS - {}", self.node_counter, (self.node_counter as f64 / ((now() - self.start_ms) as f64 / 1000.)).round());
 
         self.node_counter = 0;
         self.memo_hits = 0;
         self.fast_found_hits = 0;
-        self.memo.clear();
+        leading_move
+    }
+
+    fn start_search(&mut self, board: &Board, limits: SearchLimits) {
+        self.test_board.clone_from(board);
+        self.root_history_len = self.test_board.get_hash_history_len();
+        self.start_ms = now();
+        self.limits = limits;
+        self.terminated = false;
+        self.leading_move = None;
+    }
+
+    /// Searches odd depths from `first_depth` until a limit is hit. Helper threads pass no `on_depth`, and don't log
+    fn iterative_deepening(&mut self, first_depth: i8, mut on_depth: Option<&mut dyn FnMut(&SearchInfo)>) {
+        let is_main = on_depth.is_some();
+        for d in (first_depth..=self.limits.depth).step_by(2) {
+            if is_main {
+                console_log!("\nBegin depth {}", d);
+            }
+            unsafe {
+                self.negamax(d, -MAX_EVAL, MAX_EVAL, 0);
+            }
+
+            if let Some(on_depth) = on_depth.as_mut() {
+                if let Some((m, eval, depth)) = self.leading_move.clone() {
+                    console_log!("{}, d={}", self.test_board.stringify_move(&m), depth);
+                    if !self.terminated {
+                        on_depth(&SearchInfo { depth: d, score: eval, nodes: self.get_search_nodes(), best_move: &m, hashfull: self.memo.get_hashfull() });
+                    }
+                } else {
+                    console_log!("No leading move");
+                }
+            }
+
+            if self.terminated {
+                if is_main {
+                    console_log!("Terminated due to limits");
+                }
+                break;
+            }
+        }
+    }
+
+    /// Lazy SMP: helper threads search the same position through the shared memo, mostly filling it with results
+    /// this thread then finds instead of searching. They stop when this thread does
+    #[cfg(not(target_arch = "wasm32"))]
+    fn iterative_deepening_with_helpers(&mut self, board: &Board, on_depth: &mut dyn FnMut(&SearchInfo)) {
+        let helpers_stop = Arc::new(AtomicBool::new(false));
+        let mut helpers: Vec<Ai> = (1..self.threads)
+            .map(|_| Ai { search_nodes: self.search_nodes.clone(), ..Ai::new_with_memo(self.memo.clone()) })
+            .collect();
+
+        thread::scope(|scope| {
+            for (i, helper) in helpers.iter_mut().enumerate() {
+                let limits = SearchLimits { depth: self.limits.depth, ms_till_terminate: u128::MAX, max_nodes: u64::MAX, stop: Some(helpers_stop.clone()) };
+                helper.start_search(board, limits);
+                // Half of the helpers start a depth ahead, so the threads spread over more depths
+                let first_depth = if i % 2 == 0 { 3 } else { 1 };
+                thread::Builder::new()
+                    .stack_size(SEARCH_STACK_SIZE)
+                    .spawn_scoped(scope, move || helper.iterative_deepening(first_depth, None))
+                    .expect("Failed to start search helper thread");
+            }
+
+            self.iterative_deepening(1, Some(on_depth));
+            helpers_stop.store(true, Ordering::Relaxed);
+        });
+        console_log!("Helper nodes - {}", helpers.iter().map(|helper| helper.node_counter).sum::<u64>());
     }
 
     fn assert_king_pos(&self, player: Player) {
@@ -121,61 +299,106 @@ impl Ai {
     fn get_no_moves_eval(&mut self, alpha: i32, beta: i32) -> i32 {
         let checking_player = self.test_board.get_player_with_turn().other_player();
         if self.test_board.is_checking(checking_player) {
-            return alpha;
+            return (-MAX_EVAL + self.get_ply()).max(alpha).min(beta);
         } else {
-            if 0 <= alpha { return alpha; }
-            else if 0 >= beta { return beta; }
-            else { return 0; }
+            return self.get_draw_eval(alpha, beta);
         }
     }
 
+    #[inline]
+    fn get_draw_eval(&self, alpha: i32, beta: i32) -> i32 {
+        if 0 <= alpha { alpha }
+        else if 0 >= beta { beta }
+        else { 0 }
+    }
+
+    /// Halfmoves played from the search root
+    #[inline]
+    fn get_ply(&self) -> i32 {
+        (self.test_board.get_hash_history_len() - self.root_history_len) as i32
+    }
+
+    /// Mate scores are memoized as plies from the memoized position instead of from the root,
+    /// since the position can be reached at other plies
+    #[inline]
+    fn get_memo(&self) -> Option<MemoData> {
+        self.memo.get(self.test_board.get_hash()).map(|MemoData(score, depth, memo_type, best_move)| {
+            MemoData(adjust_mate_eval(score, self.get_ply()), depth, memo_type, best_move)
+        })
+    }
+
     #[inline]
     fn insert_memo(&mut self, memo_data: MemoData) {
-        self.memo.insert(self.test_board.get_hash(), memo_data);
+        self.set_leading_move(&memo_data);
+        let MemoData(score, depth, memo_type, best_move) = memo_data;
+        self.memo.insert(self.test_board.get_hash(), MemoData(adjust_mate_eval(score, -self.get_ply()), depth, memo_type, best_move));
+    }
+
+    /// Keeps root results with a best move. In this context, fail high means checkmate
+    fn set_leading_move(&mut self, memo_data: &MemoData) {
+        if let MemoData(score, depth, _, Some(best_move)) = memo_data {
+            if self.get_ply() == 0 {
+                self.leading_move = Some((MoveWithEval(best_move.clone(), 0), *score, *depth));
+            }
+        }
     }
 
     /// Node counter increase coupled with check to not miss an increment
     fn increment_node_check_termination(&mut self) -> bool {
         self.node_counter += 1;
-        self.terminated = self.terminated || (self.node_counter % 50000 == 0 && now() - self.start_ms > self.ms_till_terminate);
+        let unbatched_nodes = self.node_counter % SEARCH_NODES_BATCH_LEN;
+        if unbatched_nodes == 0 {
+            self.search_nodes.fetch_add(SEARCH_NODES_BATCH_LEN, Ordering::Relaxed);
+        }
+        self.terminated = self.terminated || self.get_search_nodes() >= self.limits.max_nodes || (self.node_counter % 4096 == 0 && self.is_time_or_stop());
         self.terminated
     }
 
+    /// Nodes of all threads, missing only the other threads' nodes not yet added in a batch
+    fn get_search_nodes(&self) -> u64 {
+        self.search_nodes.load(Ordering::Relaxed) + self.node_counter % SEARCH_NODES_BATCH_LEN
+    }
+
+    fn is_time_or_stop(&self) -> bool {
+        now() - self.start_ms > self.limits.ms_till_terminate || matches!(&self.limits.stop, Some(stop) if stop.load(Ordering::Relaxed))
+    }
+
     /// First tuple entry = the memoized result if any
     /// Second tuple entry = if this value exists, we can stop recursing because the full result is memoized, including the score sign
-    fn find_memo_score(&mut self, remaining_depth: i8, alpha: i32, beta: i32) -> (Option<&MemoType>, Option<i32>) {
-        if let Some(MemoData(saved_num, saved_depth, memo_type)) = self.memo.get(&self.test_board.get_hash()) {
+    fn find_memo_score(&mut self, remaining_depth: i8, alpha: i32, beta: i32) -> (Option<MemoData>, Option<i32>) {
+        if let Some(memo_data) = self.get_memo() {
+            let MemoData(saved_num, saved_depth, memo_type, _) = memo_data;
 
             // If the memoized move has the precision we want, use its score
-            if *saved_depth >= remaining_depth {
+            if saved_depth >= remaining_depth {
                 match memo_type {
-                    MemoType::Low(_) => {
-                        if *saved_num <= alpha {
+                    MemoType::Low => {
+                        if saved_num <= alpha {
                             self.memo_hits += 1;
-                            return (Some(memo_type), Some(alpha));
+                            return (Some(memo_data), Some(alpha));
                         }
                     },
-                    MemoType::High(_) => {
-                        if *saved_num >= beta { 
+                    MemoType::High => {
+                        if saved_num >= beta { 
                             self.memo_hits += 1;
-                            return (Some(memo_type), Some(beta));
+                            return (Some(memo_data), Some(beta));
                         }
                     },
-                    MemoType::Exact(_) => {
+                    MemoType::Exact => {
                         self.memo_hits += 1;
 
-                        if *saved_num < alpha {
-                            return (Some(memo_type), Some(alpha)); 
-                        } else if *saved_num > beta {
-                            return (Some(memo_type), Some(beta)); 
+                        if saved_num < alpha {
+                            return (Some(memo_data), Some(alpha)); 
+                        } else if saved_num > beta {
+                            return (Some(memo_data), Some(beta)); 
                         } else {
-                            return (Some(memo_type), Some(*saved_num)); 
+                            return (Some(memo_data), Some(saved_num)); 
                         }
                     }
                 };
             }; 
 
-            (Some(memo_type), None)
+            (Some(memo_data), None)
         } else {
             (None, None)
         }
@@ -204,19 +427,25 @@ impl Ai {
 
         if remaining_depth_opt <= 0 { return score; }
 
-        // Intuition: If static evaluation is >= beta, and pretending zugzwang doesn't apply, we stop searching assuming 
-        // more free moves will make score go even higher, despite unstable captures still existing.
-        if score >= beta { return beta; }
+        // When in check, every evasion is generated and there may be no quiet move as good as the static evaluation, so don't stand pat
+        let is_in_check = self.test_board.is_checking(self.test_board.get_player_with_turn().other_player());
+        if !is_in_check {
+            // Intuition: If static evaluation is >= beta, and pretending zugzwang doesn't apply, we stop searching assuming 
+            // more free moves will make score go even higher, despite unstable captures still existing.
+            if score >= beta { return beta; }
 
-        // Intuition: Same as beta, if static eval is X, given a free move, we expect it to be > X. Of course, if this assumption is wrong and score < X,
-        // then we are lying in that the returned score is exact because above initial alpha, but it's not true.
-        if score > alpha { alpha = score; }
+            // Intuition: Same as beta, if static eval is X, given a free move, we expect it to be > X. Of course, if this assumption is wrong and score < X,
+            // then we are lying in that the returned score is exact because above initial alpha, but it's not true.
+            if score > alpha { alpha = score; }
+        }
 
         // Generate moves and order
         self.moves_buf.write_index = moves_start;
-        self.test_board.get_checks_captures_for(self.test_board.get_player_with_turn(), &mut self.temp_moves, &mut self.moves_buf);
+        self.test_board.get_checks_captures(&mut self.moves_buf);
         let moves_end_exclusive = self.moves_buf.write_index;
-        if moves_start == moves_end_exclusive { return score; }
+        if moves_start == moves_end_exclusive {
+            return if is_in_check { self.get_no_moves_eval(alpha, beta) } else { score };
+        }
 
         evaluation::add_captures_to_evals(&self.test_board, &mut self.moves_buf, moves_start, moves_end_exclusive);
         self.moves_buf.sort_subset_by_eval(moves_start, moves_end_exclusive);
@@ -238,7 +467,7 @@ impl Ai {
                 if self.terminated { return initial_alpha; } // See (2)
                 return beta; 
             }
-            if r > alpha { alpha = score; }
+            if r > alpha { alpha = r; }
         }
 
         alpha
@@ -254,12 +483,23 @@ impl Ai {
         moves_start: usize
     ) -> i32 {
 
+        // Any repetition within the search path is scored as a draw, since the side ahead would otherwise not avoid it,
+        // and the score depends on the path so it must not go through the memo
+        if self.test_board.get_hash_history_len() > self.root_history_len && self.test_board.get_repetition_count() > 0 {
+            return self.get_draw_eval(initial_alpha, beta);
+        }
+
         if remaining_depth <= 0 {
             return self.qsearch(10, initial_alpha, beta, moves_start);
         }
 
         if self.increment_node_check_termination() { return initial_alpha; } // Chain force beta cutoff in all parents; checked by assertions (2)
 
+        // Checkmate on the 100th halfmove takes precedence, so leave positions in check to the normal search
+        if self.test_board.is_fifty_move_draw() && !self.test_board.is_checking(self.test_board.get_player_with_turn().other_player()) {
+            return self.get_draw_eval(initial_alpha, beta);
+        }
+
         let mut alpha = initial_alpha;
 
         const NEW_ALPHA_I_NEVER_SET: i32 = -1;
@@ -270,27 +510,23 @@ impl Ai {
         let mut hash_move: Option<MoveWithEval> = None;
 
         match self.find_memo_score(remaining_depth, alpha, beta) {
-            (_, Some(adjusted_score)) => { // Use memoized move
+            (memo_data, Some(adjusted_score)) => { // Use memoized move
+                if let Some(memo_data) = memo_data {
+                    self.set_leading_move(&memo_data);
+                }
                 return adjusted_score;
             },
-            (Some(memo_type), None) => { // Memoized move is not precise enough, try using it as the first best move
-
-                // Clone the move, unlike if the move is on move list, because memo updates will overwrite the same memory. Also, we can move it into the memo.
-                let move_clone = if let MemoType::Exact(m) | MemoType::High(m) = memo_type {
-                    Some(m.clone())
-                } else {
-                    // (1) For fail low memo entries, currently the move is a random move so we can't use it as the best move (but it doesn't have to be that way TODO)
-                    None
-                };
+            (Some(MemoData(_, _, _, best_move)), None) => { // Memoized move is not precise enough, try using it as the first best move
 
-                if let Some(m) = move_clone {
+                // (1) Fail low memo entries have no best move of their own, but keep the best move of an earlier search of the position if any
+                if let Some(m) = best_move.map(|m| MoveWithEval(m, 0)) {
                     // Reminder: No null window, because this is our best move candidate, hence it is not expected to fail low
                     match self.negamax_try_move(remaining_depth, alpha, false, beta, &m, moves_start) {
                         SingleMoveResult::BetaCutOff(score) => {
                             if self.terminated {
                                 return initial_alpha; // See (2)
                             } else {
-                                self.insert_memo(MemoData(score, remaining_depth, MemoType::High(m)));
+                                self.insert_memo(MemoData(score, remaining_depth, MemoType::High, Some(m.0)));
                                 return beta;
                             }
                         },
@@ -313,7 +549,7 @@ impl Ai {
 
         // Generate moves and order
         self.moves_buf.write_index = moves_start;
-        self.test_board.get_moves(&mut self.temp_moves, &mut self.moves_buf);
+        self.test_board.get_moves(&mut self.moves_buf);
         let moves_end_exclusive = self.moves_buf.write_index;
 
         // FIXME
@@ -374,14 +610,14 @@ impl Ai {
                     if self.terminated {
                         // Don't lose the best move so far during termination, but don't pretend it's the real move at this depth (hence -1 to remaining depth)
                         if new_alpha_i == NEW_ALPHA_I_HASH_MOVE {
-                            self.insert_memo(MemoData(alpha, remaining_depth - 1, MemoType::Exact(hash_move.unwrap().clone())));
+                            self.insert_memo(MemoData(alpha, remaining_depth - 1, MemoType::Exact, hash_move.map(|m| m.0)));
                         } else if new_alpha_i >= 0 {
-                            self.insert_memo(MemoData(alpha, remaining_depth - 1, MemoType::Exact(self.moves_buf.v()[new_alpha_i as usize].clone())));
+                            self.insert_memo(MemoData(alpha, remaining_depth - 1, MemoType::Exact, Some(self.moves_buf.v()[new_alpha_i as usize].0.clone())));
                         }
                         return initial_alpha; // See (2)
                     } else {
                         if less_depth_amount <= 0 {
-                            self.insert_memo(MemoData(score, remaining_depth, MemoType::High((*m).clone())));
+                            self.insert_memo(MemoData(score, remaining_depth, MemoType::High, Some((*m).0.clone())));
                             return beta;
                         } else {
                             less_depth_amount = 0;
@@ -395,12 +631,12 @@ impl Ai {
 
         assert!(!self.terminated);
         if new_alpha_i == NEW_ALPHA_I_HASH_MOVE {
-            self.insert_memo(MemoData(alpha, remaining_depth, MemoType::Exact(hash_move.unwrap())));
+            self.insert_memo(MemoData(alpha, remaining_depth, MemoType::Exact, hash_move.map(|m| m.0)));
         } else if new_alpha_i >= 0 {
-            self.insert_memo(MemoData(alpha, remaining_depth, MemoType::Exact(self.moves_buf.v()[new_alpha_i as usize].clone())));
+            self.insert_memo(MemoData(alpha, remaining_depth, MemoType::Exact, Some(self.moves_buf.v()[new_alpha_i as usize].0.clone())));
         } else {
             // See (1)
-            self.insert_memo(MemoData(alpha, remaining_depth, MemoType::Low(self.moves_buf.v()[0].clone())));
+            self.insert_memo(MemoData(alpha, remaining_depth, MemoType::Low, None));
         }
 
         alpha
@@ -452,3 +688,57 @@ impl Ai {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn scores_mates_by_distance() {
+        assert_eq!(get_mate_in(MAX_EVAL - 1), Some(1));
+        assert_eq!(get_mate_in(MAX_EVAL - 3), Some(2));
+        assert_eq!(get_mate_in(-MAX_EVAL + 2), Some(-1));
+        assert_eq!(get_mate_in(500), None);
+
+        let mut ai = Ai::new();
+        ai.set_memo_size_mb(1);
+        for (fen, depth, mate_in) in [("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 5, 2), ("7k/8/6KQ/8/8/8/8/8 b - - 0 1", 1, -1)].iter() {
+            let board = Board::from_fen(fen).unwrap();
+            let mut score = 0;
+            ai.find_move_with_info(SearchLimits::new(*depth, 10000), &board, |info| score = info.score);
+            assert_eq!(get_mate_in(score), Some(*mate_in), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn keeps_leading_move_without_memo() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut ai = Ai::new();
+        ai.start_search(&board, SearchLimits::new(3, 10000));
+        ai.iterative_deepening(1, None);
+
+        // As if other positions replaced the root entry
+        ai.set_memo_size_mb(1);
+        let (m, score, depth) = ai.leading_move.clone().unwrap();
+        assert_eq!(board.get_uci_move(&m), "a1a8");
+        assert_eq!(get_mate_in(score), Some(1));
+        assert_eq!(depth, 3);
+    }
+
+    #[test]
+    fn helper_threads_find_mate() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut ai = Ai::new();
+        ai.set_memo_size_mb(1);
+        ai.set_threads(4);
+        let m = ai.find_move(SearchLimits::new(5, 10000), &board).unwrap();
+        assert_eq!(board.get_uci_move(&m), "a1a8");
+
+        // Results are kept for the next search, which finds them instead of searching
+        let mut infos = Vec::new();
+        ai.find_move_with_info(SearchLimits::new(3, 10000), &board, |info| infos.push((info.depth, info.nodes)));
+        assert_eq!(infos.len(), 2);
+        assert!(infos.iter().all(|(_, nodes)| *nodes < 10), "{:?}", infos);
+    }
+}
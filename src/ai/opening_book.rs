@@ -0,0 +1,175 @@
+use std::fmt::{Display, Formatter, self};
+use super::super::game::board::*;
+use super::super::game::move_list::*;
+use super::super::extern_funcs::random;
+
+/// Key, move, weight and learn fields, all big endian
+const ENTRY_LEN: usize = 16;
+
+/// Index: Polyglot promotion number - 1
+static PROMOTION_LETTERS: [char; 4] = ['n', 'b', 'r', 'q'];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BookError {
+    /// (number of bytes)
+    BadLength(usize)
+}
+
+impl Display for BookError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            BookError::BadLength(len) => write!(f, "A Polyglot book is made of {} byte entries, but got {} bytes", ENTRY_LEN, len)
+        }
+    }
+}
+
+/// How to choose between the book moves of a position
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BookPolicy {
+    /// Highest weight
+    Best,
+    /// Random, with chances proportional to weights
+    Weighted
+}
+
+struct BookEntry {
+    key: u64,
+    raw_move: u16,
+    weight: u16
+}
+
+/// Polyglot `.bin` opening book
+pub struct OpeningBook {
+    /// Sorted by key
+    entries: Vec<BookEntry>,
+    policy: BookPolicy
+}
+
+/// Polyglot move in UCI notation, with castles as the king taking its own rook
+fn raw_move_to_uci(raw_move: u16) -> String {
+    let square = |bits: u16| format!("{}{}", (b'a' + (bits & 7) as u8) as char, ((bits >> 3) & 7) + 1);
+    let mut uci = format!("{}{}", square(raw_move >> 6), square(raw_move));
+    let promotion = ((raw_move >> 12) & 7) as usize;
+    if (1..=PROMOTION_LETTERS.len()).contains(&promotion) {
+        uci.push(PROMOTION_LETTERS[promotion - 1]);
+    }
+    uci
+}
+
+impl OpeningBook {
+
+    pub fn from_bytes(bytes: &[u8], policy: BookPolicy) -> Result<Self, BookError> {
+        let chunks = bytes.chunks_exact(ENTRY_LEN);
+        if !chunks.remainder().is_empty() {
+            return Err(BookError::BadLength(bytes.len()));
+        }
+        let mut entries: Vec<BookEntry> = chunks.map(|chunk| {
+            let mut key = [0u8; 8];
+            key.copy_from_slice(&chunk[0..8]);
+            BookEntry {
+                key: u64::from_be_bytes(key),
+                raw_move: u16::from_be_bytes([chunk[8], chunk[9]]),
+                weight: u16::from_be_bytes([chunk[10], chunk[11]])
+            }
+        }).collect();
+        // Books are supposed to be sorted already, but a stable sort keeps their move order if so
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Self { entries, policy })
+    }
+
+    /// Legal book moves of the position with their weights, skipping moves with weight 0
+    pub fn get_moves(&self, board: &Board) -> Vec<(MoveWithEval, u16)> {
+        let key = board.get_polyglot_hash();
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..].iter()
+            .take_while(|entry| entry.key == key)
+            .filter(|entry| entry.weight > 0)
+            // Entries from hash collisions or other variants are not legal here
+            .filter_map(|entry| board.parse_uci_move(&raw_move_to_uci(entry.raw_move)).ok().map(|m| (m, entry.weight)))
+            .collect()
+    }
+
+    /// None if the position is not in the book
+    pub fn choose_move(&self, board: &Board) -> Option<MoveWithEval> {
+        let moves = self.get_moves(board);
+        match self.policy {
+            BookPolicy::Best => moves.into_iter().max_by_key(|(_, weight)| *weight).map(|(m, _)| m),
+            BookPolicy::Weighted => {
+                let total: u32 = moves.iter().map(|(_, weight)| *weight as u32).sum();
+                let mut remaining = (random() * total as f64) as u32;
+                for (m, weight) in moves.into_iter() {
+                    if remaining < weight as u32 {
+                        return Some(m);
+                    }
+                    remaining -= weight as u32;
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// (FEN, Polyglot move, weight) to book bytes
+    fn make_book(entries: &[(&str, u16, u16)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (fen, raw_move, weight) in entries.iter() {
+            bytes.extend_from_slice(&Board::from_fen(fen).unwrap().get_polyglot_hash().to_be_bytes());
+            bytes.extend_from_slice(&raw_move.to_be_bytes());
+            bytes.extend_from_slice(&weight.to_be_bytes());
+            bytes.extend_from_slice(&[0; 4]);
+        }
+        bytes
+    }
+
+    /// Polyglot move number from squares given as (x, rank - 1)
+    fn raw_move(from: (u16, u16), to: (u16, u16)) -> u16 {
+        (from.1 << 9) | (from.0 << 6) | (to.1 << 3) | to.0
+    }
+
+    static START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn decodes_moves() {
+        assert_eq!(raw_move_to_uci(raw_move((4, 1), (4, 3))), "e2e4");
+        assert_eq!(raw_move_to_uci(raw_move((0, 6), (1, 7)) | (4 << 12)), "a7b8q");
+        assert_eq!(raw_move_to_uci(raw_move((4, 0), (7, 0))), "e1h1");
+    }
+
+    #[test]
+    fn chooses_book_moves() {
+        let bytes = make_book(&[
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", raw_move((4, 0), (7, 0)), 5),
+            (START_FEN, raw_move((3, 1), (3, 3)), 0),
+            (START_FEN, raw_move((4, 1), (4, 3)), 3),
+            (START_FEN, raw_move((6, 0), (5, 2)), 7),
+            // Illegal here
+            (START_FEN, raw_move((4, 1), (4, 4)), 100)
+        ]);
+        let book = OpeningBook::from_bytes(&bytes, BookPolicy::Best).unwrap();
+
+        let board = Board::new();
+        let ucis: Vec<String> = book.get_moves(&board).iter().map(|(m, _)| board.get_uci_move(m)).collect();
+        assert_eq!(ucis, vec!["e2e4", "g1f3"]);
+        assert_eq!(board.get_uci_move(&book.choose_move(&board).unwrap()), "g1f3");
+
+        let castle_board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert!(*book.choose_move(&castle_board).unwrap().description() == MoveDescription::Castle(CastleType::Oo));
+        assert!(book.choose_move(&Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap()).is_none());
+
+        let book = OpeningBook::from_bytes(&bytes, BookPolicy::Weighted).unwrap();
+        for _ in 0..20 {
+            let uci = board.get_uci_move(&book.choose_move(&board).unwrap());
+            assert!(uci == "e2e4" || uci == "g1f3");
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_books() {
+        assert_eq!(OpeningBook::from_bytes(&[0; 20], BookPolicy::Best).err(), Some(BookError::BadLength(20)));
+    }
+}
@@ -0,0 +1,277 @@
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use super::super::game::coords::*;
+use super::super::game::entities::*;
+use super::super::game::move_list::*;
+
+pub const DEFAULT_SIZE_MB: usize = 16;
+
+/// The first entry of a bucket is kept for the deepest result of the current search, the second is always replaced
+const BUCKET_LEN: usize = 2;
+/// Ages wrap around, they only need to tell the current search apart from older ones
+const AGE_MASK: u8 = 0x3F;
+/// Entries checked for `get_hashfull`
+const HASHFULL_SAMPLE_LEN: usize = 1000;
+
+const NO_MOVE: u16 = u16::MAX;
+const PROMOTION_KIND_START: u16 = 1;
+const CASTLE_KIND_START: u16 = 5;
+const SKIP_MOVE_KIND: u16 = 7;
+/// Index: promotion move kind - `PROMOTION_KIND_START`
+static PROMOTION_KINDS: [Piece; 4] = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+/// Starts at 1, so empty entries are never mistaken for stored ones
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoType {
+    /// Failed low, the real score is at most the stored one
+    Low = 1,
+    Exact,
+    /// Failed high, the real score is at least the stored one
+    High
+}
+
+/// (score, remaining depth searched, memo type, best move). Fail lows have no best move
+#[derive(Clone, PartialEq, Eq)]
+pub struct MemoData(pub i32, pub i8, pub MemoType, pub Option<MoveDescription>);
+
+/// 6 bit squares and a 4 bit kind: 0 for normal moves, then promotions, castles and skip moves
+fn pack_move(m: &MoveDescription) -> u16 {
+    match m {
+        MoveDescription::NormalMove(from, to) => (from.0 as u16) << 6 | to.0 as u16,
+        MoveDescription::Promotion(from, to, promote_to) => {
+            let kind = PROMOTION_KIND_START + PROMOTION_KINDS.iter().position(|piece| piece == promote_to).unwrap() as u16;
+            kind << 12 | (from.0 as u16) << 6 | to.0 as u16
+        },
+        MoveDescription::Castle(castle_type) => (CASTLE_KIND_START + *castle_type as u16) << 12,
+        MoveDescription::SkipMove => SKIP_MOVE_KIND << 12
+    }
+}
+
+fn unpack_move(packed: u16) -> Option<MoveDescription> {
+    let from = FastCoord((packed >> 6 & 0x3F) as u8);
+    let to = FastCoord((packed & 0x3F) as u8);
+    match packed >> 12 {
+        0 => Some(MoveDescription::NormalMove(from, to)),
+        kind if kind < CASTLE_KIND_START => Some(MoveDescription::Promotion(from, to, PROMOTION_KINDS[(kind - PROMOTION_KIND_START) as usize])),
+        kind if kind == CASTLE_KIND_START => Some(MoveDescription::Castle(CastleType::Oo)),
+        kind if kind == CASTLE_KIND_START + 1 => Some(MoveDescription::Castle(CastleType::Ooo)),
+        kind if kind == SKIP_MOVE_KIND => Some(MoveDescription::SkipMove),
+        _ => None
+    }
+}
+
+/// Copy of an entry, read or to be written
+#[derive(Copy, Clone, Default)]
+struct Entry {
+    /// Full board hash, 0 if empty
+    key: u64,
+    /// From the low bits: score (32), packed move (16), depth (8), age (6) and memo type (2)
+    data: u64
+}
+
+/// Entry shared between search threads without locks. The key is stored XORed with the data,
+/// so an entry torn by two threads writing at once doesn't match any key instead of giving the wrong data
+#[derive(Default)]
+struct AtomicEntry {
+    key_xor_data: AtomicU64,
+    data: AtomicU64
+}
+
+impl AtomicEntry {
+    fn load(&self) -> Entry {
+        let data = self.data.load(Ordering::Relaxed);
+        Entry { key: self.key_xor_data.load(Ordering::Relaxed) ^ data, data }
+    }
+
+    fn store(&self, entry: Entry) {
+        self.key_xor_data.store(entry.key ^ entry.data, Ordering::Relaxed);
+        self.data.store(entry.data, Ordering::Relaxed);
+    }
+}
+
+impl Entry {
+    fn new(key: u64, memo_data: &MemoData, age: u8) -> Self {
+        let MemoData(score, depth, memo_type, best_move) = memo_data;
+        let packed_move = best_move.as_ref().map_or(NO_MOVE, pack_move);
+        Self {
+            key,
+            data: (*score as u32 as u64) | (packed_move as u64) << 32 | (*depth as u8 as u64) << 48 | ((age << 2 | *memo_type as u8) as u64) << 56
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data == 0
+    }
+
+    fn get_depth(&self) -> i8 {
+        (self.data >> 48) as u8 as i8
+    }
+
+    fn get_age(&self) -> u8 {
+        (self.data >> 58) as u8
+    }
+
+    fn get_packed_move(&self) -> u16 {
+        (self.data >> 32) as u16
+    }
+
+    fn to_memo_data(self) -> MemoData {
+        let memo_type = match (self.data >> 56) as u8 & 3 {
+            1 => MemoType::Low,
+            2 => MemoType::Exact,
+            _ => MemoType::High
+        };
+        MemoData(self.data as u32 as i32, self.get_depth(), memo_type, unpack_move(self.get_packed_move()))
+    }
+}
+
+/// Fixed size hash table of search results, kept between searches and shared by search threads
+pub struct TranspositionTable {
+    buckets: Vec<[AtomicEntry; BUCKET_LEN]>,
+    /// Number of buckets - 1, the number being a power of two
+    index_mask: usize,
+    age: AtomicU8
+}
+
+impl TranspositionTable {
+
+    /// Rounds down to a power of two number of buckets
+    pub fn new(size_mb: usize) -> Self {
+        let max_len = (size_mb * 1024 * 1024 / size_of::<[AtomicEntry; BUCKET_LEN]>()).max(1);
+        let len = 1 << (usize::BITS - 1 - max_len.leading_zeros());
+        Self {
+            buckets: (0..len).map(|_| Default::default()).collect(),
+            index_mask: len - 1,
+            age: AtomicU8::new(0)
+        }
+    }
+
+    /// Precondition: no search is running
+    pub fn clear(&self) {
+        for entry in self.buckets.iter().flat_map(|bucket| bucket.iter()) {
+            entry.store(Entry::default());
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    /// Call before each search, so results of older searches are replaced first
+    pub fn new_search(&self) {
+        self.age.store((self.get_age() + 1) & AGE_MASK, Ordering::Relaxed);
+    }
+
+    fn get_age(&self) -> u8 {
+        self.age.load(Ordering::Relaxed)
+    }
+
+    fn load_bucket(&self, hash: u64) -> [Entry; BUCKET_LEN] {
+        let bucket = &self.buckets[hash as usize & self.index_mask];
+        std::array::from_fn(|i| bucket[i].load())
+    }
+
+    pub fn get(&self, hash: u64) -> Option<MemoData> {
+        self.load_bucket(hash).iter()
+            .find(|entry| entry.key == hash && !entry.is_empty())
+            .map(|entry| entry.to_memo_data())
+    }
+
+    pub fn insert(&self, hash: u64, memo_data: MemoData) {
+        let age = self.get_age();
+        let bucket = self.load_bucket(hash);
+
+        let i = if let Some(i) = bucket.iter().position(|entry| entry.key == hash && !entry.is_empty()) {
+            i
+        } else if bucket[0].get_age() != age || memo_data.1 >= bucket[0].get_depth() {
+            0
+        } else {
+            1
+        };
+
+        let mut entry = Entry::new(hash, &memo_data, age);
+        // A fail low of the same position has no move, but the older best move is still the best guess to try first
+        if memo_data.3.is_none() && bucket[i].key == hash {
+            let old_packed_move = bucket[i].get_packed_move();
+            entry.data = entry.data & !(0xFFFF << 32) | (old_packed_move as u64) << 32;
+        }
+        self.buckets[hash as usize & self.index_mask][i].store(entry);
+    }
+
+    /// Permille of sampled entries written in the current search, like the UCI hashfull
+    pub fn get_hashfull(&self) -> u32 {
+        let age = self.get_age();
+        let entries = self.buckets.iter().flat_map(|bucket| bucket.iter()).take(HASHFULL_SAMPLE_LEN);
+        let sample_len = entries.clone().count();
+        let used = entries.map(|entry| entry.load()).filter(|entry| !entry.is_empty() && entry.get_age() == age).count();
+        (used * 1000 / sample_len) as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn packs_moves() {
+        let moves = [
+            MoveDescription::NormalMove(FastCoord(0), FastCoord(63)),
+            MoveDescription::Promotion(FastCoord(12), FastCoord(4), Piece::Knight),
+            MoveDescription::Promotion(FastCoord(52), FastCoord(61), Piece::Queen),
+            MoveDescription::Castle(CastleType::Oo),
+            MoveDescription::Castle(CastleType::Ooo),
+            MoveDescription::SkipMove
+        ];
+        for m in moves.iter() {
+            assert!(unpack_move(pack_move(m)).as_ref() == Some(m));
+        }
+        assert!(unpack_move(NO_MOVE).is_none());
+    }
+
+    #[test]
+    fn stores_entries() {
+        let table = TranspositionTable::new(1);
+        let m = MoveDescription::NormalMove(FastCoord(52), FastCoord(36));
+        table.insert(123, MemoData(-50, 4, MemoType::High, Some(m.clone())));
+        assert!(table.get(123) == Some(MemoData(-50, 4, MemoType::High, Some(m.clone()))));
+        assert!(table.get(124).is_none());
+
+        // Fail lows keep the previous best move
+        table.insert(123, MemoData(-70, 6, MemoType::Low, None));
+        assert!(table.get(123) == Some(MemoData(-70, 6, MemoType::Low, Some(m))));
+
+        table.clear();
+        assert!(table.get(123).is_none());
+    }
+
+    #[test]
+    fn replaces_shallow_and_old_entries() {
+        let table = TranspositionTable::new(1);
+        let bucket_len = table.buckets.len() as u64;
+        let keys: Vec<u64> = (1..=4).map(|i| i * bucket_len + 7).collect();
+
+        table.insert(keys[0], MemoData(1, 5, MemoType::Exact, None));
+        table.insert(keys[1], MemoData(2, 3, MemoType::Exact, None));
+        table.insert(keys[2], MemoData(3, 2, MemoType::Exact, None));
+        // The deepest entry stays, the always replace entry is overwritten
+        assert!(table.get(keys[0]).is_some());
+        assert!(table.get(keys[1]).is_none());
+        assert!(table.get(keys[2]).is_some());
+        assert_eq!(table.get_hashfull(), 2);
+
+        table.new_search();
+        table.insert(keys[3], MemoData(4, 1, MemoType::Exact, None));
+        assert!(table.get(keys[0]).is_none());
+        assert!(table.get(keys[3]) == Some(MemoData(4, 1, MemoType::Exact, None)));
+    }
+
+    #[test]
+    fn reports_hashfull() {
+        let table = TranspositionTable::new(1);
+        for key in 1..=250 {
+            table.insert(key, MemoData(0, 1, MemoType::Exact, None));
+        }
+        assert_eq!(table.get_hashfull(), 250);
+        table.new_search();
+        assert_eq!(table.get_hashfull(), 0);
+    }
+}
@@ -0,0 +1,4 @@
+fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    ljenks_chess::uci::run();
+}
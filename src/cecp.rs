@@ -0,0 +1,393 @@
+//! XBoard/CECP protocol over standard input and output, for tools and older GUIs which don't speak UCI
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use super::ai::*;
+use super::engine_io::*;
+use super::game::board::*;
+use super::game::entities::*;
+use super::game::game_status::*;
+use super::game::move_history::*;
+use super::game::move_list::*;
+use super::game::pgn::get_result_string;
+
+/// Commands and finished searches, handled one at a time by `run`, so commands like `?` work while thinking
+enum Event {
+    Line(String),
+    /// (search id)
+    SearchDone(u32),
+    EndOfInput
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TimeControl {
+    /// (moves per period or 0 for the whole game, base ms, increment ms), from `level`
+    Level(u64, u64, u64),
+    /// (ms per move), from `st`
+    MoveTime(u64)
+}
+
+/// `level <moves per period> <base> <increment>`. The base is in minutes or minutes:seconds, the increment in seconds
+fn parse_level(args: &[&str]) -> Option<TimeControl> {
+    if args.len() != 3 {
+        return None;
+    }
+    let moves_per_period = args[0].parse::<u64>().ok()?;
+    let base_ms = match args[1].split_once(':') {
+        Some((minutes, seconds)) => minutes.parse::<u64>().ok()? * 60_000 + seconds.parse::<u64>().ok()? * 1000,
+        None => args[1].parse::<u64>().ok()? * 60_000
+    };
+    let increment_ms = (args[2].parse::<f64>().ok()? * 1000.) as u64;
+    Some(TimeControl::Level(moves_per_period, base_ms, increment_ms))
+}
+
+/// Centipawns, or 100000 + N for a mate in N moves and -100000 - N when getting mated, which XBoard shows as mates
+fn get_xboard_score(score: i32) -> i32 {
+    match get_mate_in(score) {
+        Some(moves) if moves > 0 => 100000 + moves,
+        Some(moves) => -100000 + moves,
+        None => score
+    }
+}
+
+/// Game over message, eg. `1-0 {White mates}`
+fn get_result_message(status: GameStatus) -> String {
+    let reason = match status {
+        GameStatus::Ongoing => "",
+        GameStatus::Checkmate(Player::White) => "White mates",
+        GameStatus::Checkmate(Player::Black) => "Black mates",
+        GameStatus::Stalemate => "Stalemate",
+        GameStatus::Draw(DrawReason::Repetition) => "Draw by repetition",
+        GameStatus::Draw(DrawReason::FiftyMove) => "Draw by fifty move rule",
+        GameStatus::Draw(DrawReason::InsufficientMaterial) => "Draw by insufficient material"
+    };
+    format!("{} {{{}}}", get_result_string(status), reason)
+}
+
+struct Search {
+    /// Gives back the AI and its move
+    handle: JoinHandle<(Ai, Option<MoveWithEval>)>,
+    stop: Arc<AtomicBool>,
+    /// Tells apart the `SearchDone` events of abandoned searches
+    id: u32
+}
+
+struct CecpEngine {
+    board: Board,
+    history: MoveHistory,
+    /// Lent to the search thread while thinking
+    ai: Option<Ai>,
+    search: Option<Search>,
+    search_count: u32,
+    /// None in force mode, where moves are only played as given
+    engine_player: Option<Player>,
+    depth: i8,
+    time_control: TimeControl,
+    /// Engine clock from `time`
+    time_left_ms: Option<u64>,
+    /// Whether to print thinking output
+    post: bool,
+    events: Sender<Event>
+}
+
+impl CecpEngine {
+
+    fn new(events: Sender<Event>) -> Self {
+        let board = Board::new();
+        Self {
+            history: MoveHistory::new(&board),
+            board,
+            ai: Some(Ai::new()),
+            search: None,
+            search_count: 0,
+            engine_player: Some(Player::Black),
+            depth: MAX_DEPTH,
+            time_control: TimeControl::Level(40, 5 * 60_000, 0),
+            time_left_ms: None,
+            post: false,
+            events
+        }
+    }
+
+    fn set_board(&mut self, board: Board) {
+        self.history = MoveHistory::new(&board);
+        self.board = board;
+    }
+
+    fn get_move_time_ms(&self) -> u64 {
+        match self.time_control {
+            TimeControl::MoveTime(ms) => ms,
+            TimeControl::Level(moves_per_period, base_ms, increment_ms) => {
+                let moves_to_go = if moves_per_period == 0 {
+                    DEFAULT_MOVES_TO_GO
+                } else {
+                    moves_per_period - (self.board.get_fullmove_number() as u64 - 1) % moves_per_period
+                };
+                get_move_time_ms(self.time_left_ms.unwrap_or(base_ms), increment_ms, moves_to_go)
+            }
+        }
+    }
+
+    /// Starts a search if it is the engine's turn and the game is not over
+    fn think_if_engine_turn(&mut self) {
+        if self.search.is_some() || self.engine_player != Some(self.board.get_player_with_turn()) {
+            return;
+        }
+        if self.board.get_game_status(&mut MoveList::new(100)) != GameStatus::Ongoing {
+            return;
+        }
+
+        self.search_count += 1;
+        let id = self.search_count;
+        let stop = Arc::new(AtomicBool::new(false));
+        let limits = SearchLimits { depth: self.depth, ms_till_terminate: self.get_move_time_ms() as u128, max_nodes: u64::MAX, stop: Some(stop.clone()) };
+        let mut ai = self.ai.take().unwrap();
+        let board = self.board.clone();
+        let post = self.post;
+        let events = self.events.clone();
+
+        let handle = thread::Builder::new()
+            .stack_size(SEARCH_STACK_SIZE)
+            .spawn(move || {
+                let start = Instant::now();
+                let best_move = ai.find_move_with_info(limits, &board, |info| {
+                    if post {
+                        // Time is in centiseconds
+                        send(&format!("{} {} {} {} {}", info.depth, get_xboard_score(info.score), start.elapsed().as_millis() / 10,
+                            info.nodes, board.get_uci_move(info.best_move)));
+                    }
+                });
+                let _ = events.send(Event::SearchDone(id));
+                (ai, best_move)
+            })
+            .expect("Failed to start search thread");
+        self.search = Some(Search { handle, stop, id });
+    }
+
+    /// Waits for any search. Its move is played if `play_move`, otherwise it's abandoned
+    fn finish_search(&mut self, play_move: bool) {
+        if let Some(search) = self.search.take() {
+            if !play_move {
+                search.stop.store(true, Ordering::Relaxed);
+            }
+            let (ai, best_move) = search.handle.join().expect("Search thread panicked");
+            self.ai = Some(ai);
+            if let (true, Some(m)) = (play_move, best_move) {
+                send(&format!("move {}", self.board.get_uci_move(&m)));
+                self.play(&m);
+            }
+        }
+    }
+
+    /// Plays a legal move and announces the result if the game ends
+    fn play(&mut self, m: &MoveWithEval) {
+        self.history.play(&mut self.board, m);
+        let status = self.board.get_game_status(&mut MoveList::new(100));
+        if status != GameStatus::Ongoing {
+            send(&get_result_message(status));
+            self.engine_player = None;
+        }
+    }
+
+    /// Coordinate notation like e2e4 and e7e8q, with SAN accepted too
+    fn user_move(&mut self, s: &str) {
+        match self.board.parse_uci_move(s).or_else(|_| self.board.parse_san(s)) {
+            Ok(m) => {
+                self.play(&m);
+                self.think_if_engine_turn();
+            },
+            Err(_) => send(&format!("Illegal move: {}", s))
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) -> bool {
+        match event {
+            Event::Line(line) => self.handle_command(&line),
+            Event::SearchDone(id) => {
+                if matches!(&self.search, Some(search) if search.id == id) {
+                    self.finish_search(true);
+                }
+                true
+            },
+            Event::EndOfInput => false
+        }
+    }
+
+    /// False when the engine should quit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words.first() {
+            Some(command) => *command,
+            None => return true
+        };
+        let args = &words[1..];
+
+        // Thinking stops for anything which changes the game. `?` plays the best move found so far
+        match command {
+            "?" => {
+                if let Some(search) = &self.search {
+                    search.stop.store(true, Ordering::Relaxed);
+                }
+                return true;
+            },
+            "new" | "force" | "setboard" | "undo" | "remove" | "result" | "usermove" | "memory" | "cores" | "quit" => self.finish_search(false),
+            _ => {}
+        }
+
+        match command {
+            "protover" => send(&format!("feature myname=\"{}\" usermove=1 setboard=1 ping=1 memory=1 smp=1 colors=0 sigint=0 sigterm=0 done=1", ENGINE_NAME)),
+            "new" => {
+                self.set_board(Board::new());
+                self.engine_player = Some(Player::Black);
+                self.depth = MAX_DEPTH;
+                self.time_left_ms = None;
+                self.ai.as_mut().unwrap().clear_memo();
+            },
+            "force" | "result" => self.engine_player = None,
+            "go" => {
+                self.engine_player = Some(self.board.get_player_with_turn());
+                self.think_if_engine_turn();
+            },
+            "playother" => self.engine_player = Some(self.board.get_player_with_turn().other_player()),
+            "usermove" => match args.first() {
+                Some(s) => self.user_move(s),
+                None => send("Error (missing move): usermove")
+            },
+            "setboard" => match Board::from_fen(&args.join(" ")) {
+                Ok(board) => self.set_board(board),
+                Err(e) => send(&format!("tellusererror Illegal position: {}", e))
+            },
+            "undo" => {
+                self.history.undo(&mut self.board);
+            },
+            "remove" => {
+                self.history.undo(&mut self.board);
+                self.history.undo(&mut self.board);
+            },
+            "level" => match parse_level(args) {
+                Some(time_control) => self.time_control = time_control,
+                None => send(&format!("Error (bad time control): {}", line))
+            },
+            "st" => match args.first().and_then(|s| s.parse::<f64>().ok()) {
+                Some(seconds) => self.time_control = TimeControl::MoveTime((seconds * 1000.) as u64),
+                None => send(&format!("Error (bad time): {}", line))
+            },
+            "sd" => match args.first().and_then(|s| s.parse::<u64>().ok()) {
+                Some(depth) => self.depth = get_searched_depth(depth.clamp(1, MAX_DEPTH as u64) as i8),
+                None => send(&format!("Error (bad depth): {}", line))
+            },
+            // Centiseconds
+            "time" => self.time_left_ms = args.first().and_then(|s| s.parse::<u64>().ok()).map(|cs| cs * 10),
+            // MB for the transposition table, which is all the memory worth configuring
+            "memory" => match args.first().and_then(|s| s.parse::<usize>().ok()) {
+                Some(size_mb) => self.ai.as_mut().unwrap().set_memo_size_mb(size_mb.max(1)),
+                None => send(&format!("Error (bad memory size): {}", line))
+            },
+            "cores" => match args.first().and_then(|s| s.parse::<usize>().ok()) {
+                Some(threads) => self.ai.as_mut().unwrap().set_threads(threads.max(1)),
+                None => send(&format!("Error (bad number of cores): {}", line))
+            },
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "ping" => send(&format!("pong {}", args.join(" "))),
+            "quit" => return false,
+            // Accepted, but they don't change how the engine plays
+            "xboard" | "accepted" | "rejected" | "variant" | "random" | "hard" | "easy" | "computer" | "name" |
+                "rating" | "otim" | "ics" | "draw" => {},
+            _ => send(&format!("Error (unknown command): {}", command))
+        }
+        true
+    }
+}
+
+/// Plays as a CECP engine until `quit` or the end of input. Input is read on its own thread, so commands are handled while thinking
+pub fn run() {
+    let (events, receiver) = mpsc::channel();
+    let input_events = events.clone();
+    thread::spawn(move || {
+        read_lines(|line| input_events.send(Event::Line(line)).is_ok());
+        let _ = input_events.send(Event::EndOfInput);
+    });
+
+    let mut engine = CecpEngine::new(events);
+    for event in receiver.iter() {
+        if !engine.handle_event(event) {
+            break;
+        }
+    }
+    engine.finish_search(false);
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::sync::mpsc::Receiver;
+
+    fn new_engine() -> (CecpEngine, Receiver<Event>) {
+        let (events, receiver) = mpsc::channel();
+        (CecpEngine::new(events), receiver)
+    }
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!(parse_level(&["40", "5", "0"]), Some(TimeControl::Level(40, 300_000, 0)));
+        assert_eq!(parse_level(&["0", "2:30", "1.5"]), Some(TimeControl::Level(0, 150_000, 1500)));
+        assert_eq!(parse_level(&["0", "2:xx", "1"]), None);
+        assert_eq!(parse_level(&["40", "5"]), None);
+    }
+
+    #[test]
+    fn converts_mate_scores() {
+        assert_eq!(get_xboard_score(-35), -35);
+        let mut ai = Ai::new();
+        for (fen, score) in [("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 100001), ("7k/8/6KQ/8/8/8/8/8 b - - 0 1", -100001)].iter() {
+            let mut search_score = 0;
+            ai.find_move_with_info(SearchLimits::new(1, 10000), &Board::from_fen(fen).unwrap(), |info| search_score = info.score);
+            assert_eq!(get_xboard_score(search_score), *score, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn plays_moves_in_force_mode() {
+        let (mut engine, _receiver) = new_engine();
+        for line in ["new", "force", "usermove e2e4", "usermove e5", "usermove g1f3", "usermove e2e4"].iter() {
+            assert!(engine.handle_command(line));
+        }
+        assert_eq!(engine.board.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        assert!(engine.search.is_none());
+
+        engine.handle_command("remove");
+        assert_eq!(engine.board.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        engine.handle_command("undo");
+        assert_eq!(engine.board.to_fen(), Board::new().to_fen());
+
+        engine.handle_command("setboard 8/8/8");
+        assert_eq!(engine.board.to_fen(), Board::new().to_fen());
+    }
+
+    #[test]
+    fn replies_to_user_moves() {
+        let (mut engine, receiver) = new_engine();
+        engine.handle_command("new");
+        engine.handle_command("st 0.1");
+        engine.handle_command("post");
+        engine.handle_command("setboard 6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 1");
+        engine.handle_command("playother");
+        assert!(engine.search.is_none());
+
+        engine.handle_command("usermove g8h8");
+        assert!(engine.search.is_some());
+        while engine.search.is_some() {
+            let event = receiver.recv().unwrap();
+            assert!(engine.handle_event(event));
+        }
+        assert_eq!(engine.history.get_sans(), vec!["Kh8", "Ra8#"]);
+        // Game over
+        assert_eq!(engine.engine_player, None);
+        assert!(!engine.handle_command("quit"));
+    }
+}
@@ -0,0 +1,32 @@
+//! What the UCI and CECP engines share: their name, defaults and line based standard input and output
+
+use std::io::{self, BufRead, Write};
+
+pub static ENGINE_NAME: &str = "ljenks-chess";
+pub static ENGINE_AUTHOR: &str = "starqi";
+
+/// Depth limit when the GUI sets none
+pub static MAX_DEPTH: i8 = 99;
+/// Moves the remaining clock time is split between, when the GUI doesn't say how many moves are left
+pub static DEFAULT_MOVES_TO_GO: u64 = 30;
+
+/// Writes a line and flushes it, since GUIs wait for whole lines
+pub fn send(line: &str) {
+    let mut stdout = io::stdout();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+/// Calls `handle` with each line of standard input, until it returns false or the input ends
+pub fn read_lines(mut handle: impl FnMut(String) -> bool) {
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(line) => {
+                if !handle(line) {
+                    return;
+                }
+            },
+            Err(_) => return
+        }
+    }
+}
@@ -1,9 +1,8 @@
-#[cfg(test)]
-use rand::prelude::*;
-#[cfg(test)]
-use std::time::Instant;
+//! Platform services the engine needs: logging, randomness and a clock.
+//! They are JS imports on `wasm32`, and use the standard library and `rand` on native targets, eg. the UCI engine and tests.
 
-mod definitions {
+#[cfg(target_arch = "wasm32")]
+mod platform {
     use wasm_bindgen::prelude::*;
 
     #[wasm_bindgen]
@@ -18,47 +17,80 @@ mod definitions {
         #[wasm_bindgen(js_namespace = Math)]
         pub fn random() -> f64;
 
-        #[wasm_bindgen(js_namespace = Date)]
-        pub fn now() -> u32;
+        /// Unlike `Date.now`, monotonic and small enough to not lose precision
+        #[wasm_bindgen(js_namespace = performance, js_name = now)]
+        pub fn performance_now() -> f64;
     }
-}
 
-#[cfg(test)]
-pub fn log(s: &str) {
-    println!("{}", s);
+    pub fn now() -> u128 {
+        performance_now() as u128
+    }
 }
 
-#[cfg(not(test))]
-pub fn log(s: &str) {
-    definitions::log(s);
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use rand::prelude::*;
+    use std::time::Instant;
+
+    lazy_static! {
+        static ref START: Instant = Instant::now();
+    }
+
+    /// Standard output is reserved for protocols like UCI
+    pub fn log(s: &str) {
+        eprintln!("{}", s);
+    }
+
+    pub fn error(s: &str) {
+        eprintln!("{}", s);
+    }
+
+    pub fn random() -> f64 {
+        thread_rng().gen()
+    }
+
+    pub fn now() -> u128 {
+        START.elapsed().as_millis()
+    }
 }
 
-#[cfg(test)]
-pub fn error(s: &str) {
-    eprintln!("{}", s);
+pub fn log(s: &str) {
+    platform::log(s);
 }
 
-#[cfg(not(test))]
 pub fn error(s: &str) {
-    definitions::error(s);
+    platform::error(s);
 }
 
-#[cfg(test)]
+/// In [0, 1)
 pub fn random() -> f64 {
-    thread_rng().gen()
+    platform::random()
 }
 
-#[cfg(not(test))]
-pub fn random() -> f64 {
-    definitions::random()
+/// Monotonic time in ms, from an arbitrary start. Only differences between calls are meaningful
+pub fn now() -> u128 {
+    platform::now()
 }
 
 #[cfg(test)]
-pub fn now() -> u128 {
-    Instant::now().elapsed().as_millis()
-}
+mod test {
 
-#[cfg(not(test))]
-pub fn now() -> u128 {
-    definitions::now() as u128
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn now_advances() {
+        let start = now();
+        thread::sleep(Duration::from_millis(20));
+        let elapsed = now() - start;
+        assert!((20..1000).contains(&elapsed), "{}", elapsed);
+    }
+
+    #[test]
+    fn random_in_range() {
+        for _ in 0..100 {
+            assert!((0.0..1.0).contains(&random()));
+        }
+    }
 }
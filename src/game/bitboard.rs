@@ -41,7 +41,12 @@ impl Bitboard {
 
     #[inline]
     pub fn is_set(&self, x: u8, y: u8) -> bool {
-        self.0 & (1 << (63 - (y * 8 + x))) != 0
+        self.is_set_index(y * 8 + x)
+    }
+
+    #[inline]
+    pub fn is_set_index(&self, index: u8) -> bool {
+        self.0 & (1 << (63 - index)) != 0
     }
 
     #[inline]
@@ -1,13 +1,68 @@
 use super::bitboard::*;
 use super::super::*;
 
-#[repr(u8)]
-pub enum RayDirection {
-    Left = 0, LeftTop, Top, RightTop, Right, RightBottom, Bottom, LeftBottom
+/// Directions as (dx, dy)
+static ROOK_DIRECTIONS: [(i8, i8); 4] = [(-1, 0), (0, -1), (1, 0), (0, 1)];
+static BISHOP_DIRECTIONS: [(i8, i8); 4] = [(-1, -1), (1, -1), (1, 1), (-1, 1)];
+/// Index = square. Found by the `find_magics` test, since searching for them at startup is slow
+static ROOK_MAGICS: [u64; 64] = [
+    0x0004022185040042, 0x01a0221039008804, 0x024100040008a251, 0x3012000904102002,
+    0x006a004008201106, 0x092040100a002082, 0x0020804001002011, 0x0044b10480044021,
+    0x1042006100840200, 0x1005800200010080, 0x120a00051008e200, 0x0002080011010500,
+    0x0412811004880080, 0x0001084010200100, 0x8642400221048100, 0x0130400280092080,
+    0x80104082450a0004, 0x0002000401420088, 0x941a001020040400, 0x80c0080005010010,
+    0x608c100008008080, 0x0002004820820010, 0x2180500020024000, 0x0180002001d14000,
+    0x4208006902000084, 0xa020880204002110, 0x00001020080104c0, 0x0824008008080040,
+    0x0010008010800804, 0x0810801000802004, 0x000040010100208c, 0x2020804000800020,
+    0x0001288200041041, 0x4001000100040200, 0x4a02008080040002, 0x0014040080080080,
+    0x1830080080100082, 0x0010804200201200, 0x0903400280200081, 0x0040400080208000,
+    0x0002020001009044, 0x0000440002500881, 0x8082080120104004, 0x0008008008040080,
+    0x2010008010800800, 0x0010150020010240, 0x0010004000200040, 0x0c61050020800040,
+    0x24c1002548830002, 0x3002000801040200, 0x0202808004001200, 0x0002000820060010,
+    0x0020801000800800, 0xa200802000100081, 0x80a1002081004000, 0x0208800090400020,
+    0x0200020081004824, 0x4200008200082104, 0x0e00020010880441, 0x11800401800a0800,
+    0x0100100004200901, 0x0200220040800810, 0x0240044020001008, 0x2280001020400080
+];
+/// Index = square
+static BISHOP_MAGICS: [u64; 64] = [
+    0x8920181082a40040, 0x1890100c08648420, 0x10000104601c0110, 0x0001104011020200,
+    0x10e1000000420219, 0x1010001094008800, 0x0001820201210900, 0x0014808888014040,
+    0x0042080250820800, 0x008820c852004000, 0x44b0409002008804, 0x0012644008222000,
+    0x0088222284044020, 0x0000008208290260, 0x1008404814300100, 0x8002011028848804,
+    0x0001041410901840, 0x000481181a148d00, 0x0820009032400480, 0x0201280101021010,
+    0x3a12002204200800, 0x0209084412025000, 0x0002680804082880, 0xaa0211200a082020,
+    0x8081221a208a0108, 0x0026098400030420, 0x0040810200410080, 0x1004040401001100,
+    0x8120020080180082, 0x2042007001060080, 0x2121300201108450, 0x0004030800401000,
+    0x1282244020804800, 0x00868402020b0480, 0x0028020000c15204, 0x4103010000444000,
+    0x401a0080080084c0, 0x00c928041020c0c0, 0x0982602808080080, 0x8002900040042800,
+    0x4e92043846220100, 0x0000a03848241000, 0x5001002e1000a402, 0x0028208c02080801,
+    0x004400a124008090, 0x0002040c00360200, 0x8430000411280902, 0x2110904010010100,
+    0x2401508404010400, 0x0040108404024000, 0x0010020111081132, 0x14a8840504000290,
+    0x4202110410801000, 0x00100800d4008400, 0x4a51150408044102, 0x020d042404045410,
+    0x0285004802213000, 0x40011c1004840200, 0x2122121004002840, 0x0601104002010004,
+    0x0002408900000516, 0x0842008103024200, 0x28200440c6810024, 0x0010200124018b10
+];
+
+/// Fancy magic bitboard lookup for one square, https://www.chessprogramming.org/Magic_Bitboards
+#[derive(Copy, Clone, Default)]
+struct Magic {
+    /// Squares whose blockers can change the attacks, which excludes the board edges
+    mask: Bitboard,
+    magic: u64,
+    shift: u8,
+    /// Start of this square's attacks in `slider_attacks`
+    offset: usize
+}
+
+impl Magic {
+    #[inline]
+    fn get_index(&self, occupancy: &Bitboard) -> usize {
+        self.offset + ((occupancy.0 & self.mask.0).wrapping_mul(self.magic) >> self.shift) as usize
+    }
 }
 
 pub struct BitboardPresets {
-    /// First index = `RayDirection` enum order
+    /// First index = direction: left, left top, top, right top, right, right bottom, bottom, left bottom
     pub rays: [[Bitboard; 64]; 8],
     pub knight_jumps: [Bitboard; 64],
     /// First index = `Player` enum order
@@ -15,10 +70,16 @@ pub struct BitboardPresets {
     /// First index = `Player` enum order
     pub pawn_captures: [[Bitboard; 64]; 2],
     pub king_moves: [Bitboard; 64],
-    /// Index = LSB, MSB
-    pub ensure_blocker: [Bitboard; 2],
+    /// Index = square
+    rook_magics: [Magic; 64],
+    /// Index = square
+    bishop_magics: [Magic; 64],
+    /// Rook and bishop attacks for every relevant occupancy of every square, see `Magic`
+    slider_attacks: Vec<Bitboard>,
     /// Index = `Player` enum order
     pub opponent_squares: [Bitboard; 2],
+    /// Index = `Player` enum order
+    pub promotion_rows: [Bitboard; 2],
     pub central_squares: Bitboard,
     pub debruijn_indices: [u8; 64],
     pub debruijn_sequence: u64
@@ -26,6 +87,10 @@ pub struct BitboardPresets {
 
 impl BitboardPresets {
     pub fn new() -> BitboardPresets {
+        let mut slider_attacks = Vec::new();
+        let rook_magics = make_magic_lookup(&ROOK_DIRECTIONS, &ROOK_MAGICS, &mut slider_attacks);
+        let bishop_magics = make_magic_lookup(&BISHOP_DIRECTIONS, &BISHOP_MAGICS, &mut slider_attacks);
+
         BitboardPresets {
             rays: [
                 make_ray_lookup(-1, 0), make_ray_lookup(-1, -1), make_ray_lookup(0, -1), make_ray_lookup(1, -1),
@@ -35,8 +100,11 @@ impl BitboardPresets {
             pawn_pushes: [make_pawn_lookup(-1, 6), make_pawn_lookup(1, 1)],
             pawn_captures: [make_pawn_capture_lookup(-1), make_pawn_capture_lookup(1)],
             king_moves: make_king_lookup(),
-            ensure_blocker: [Bitboard(1u64 << 63), Bitboard(1)],
+            rook_magics,
+            bishop_magics,
+            slider_attacks,
             opponent_squares: [bitboard_union!(make_line(0) , make_line(1) , make_line(2)), bitboard_union!(make_line(7), make_line(6), make_line(5))],
+            promotion_rows: [make_line(0), make_line(7)],
             central_squares: make_center_sqs(),
 
             // Use existing sequence but convert to index 0 = a8, https://www.chessprogramming.org/BitScan#De_Bruijn_Multiplication
@@ -45,6 +113,81 @@ impl BitboardPresets {
             debruijn_sequence: 0x03f79d71b4cb0a89
         }
     }
+
+    /// Squares a rook at `index` attacks, up to and including the first blocker in each direction
+    #[inline]
+    pub fn rook_attacks(&self, index: u8, occupancy: &Bitboard) -> Bitboard {
+        self.slider_attacks[self.rook_magics[index as usize].get_index(occupancy)]
+    }
+
+    /// Squares a bishop at `index` attacks, up to and including the first blocker in each direction
+    #[inline]
+    pub fn bishop_attacks(&self, index: u8, occupancy: &Bitboard) -> Bitboard {
+        self.slider_attacks[self.bishop_magics[index as usize].get_index(occupancy)]
+    }
+}
+
+/// Appends the attacks of every square to `slider_attacks`, at the indices given by `magics`
+fn make_magic_lookup(directions: &[(i8, i8)], magics: &[u64; 64], slider_attacks: &mut Vec<Bitboard>) -> [Magic; 64] {
+    let mut result = [Magic::default(); 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            let mask = make_magic_mask(x, y, directions);
+            let magic = Magic {
+                mask,
+                magic: magics[(y * 8 + x) as usize],
+                shift: 64 - mask.pop_count(),
+                offset: slider_attacks.len()
+            };
+
+            slider_attacks.resize(magic.offset + (1 << mask.pop_count()), Bitboard(0));
+            for_each_subset(&mask, |occupancy| {
+                // Collisions have the same attacks, otherwise the magic would be invalid
+                slider_attacks[magic.get_index(&occupancy)] = slow_slider_attacks(x, y, directions, &occupancy);
+            });
+            result[(y * 8 + x) as usize] = magic;
+        }
+    }
+    result
+}
+
+/// Includes the empty set, https://www.chessprogramming.org/Traversing_Subsets_of_a_Set
+fn for_each_subset(mask: &Bitboard, mut cb: impl FnMut(Bitboard)) {
+    let mut subset = 0u64;
+    loop {
+        cb(Bitboard(subset));
+        subset = subset.wrapping_sub(mask.0) & mask.0;
+        if subset == 0 { break; }
+    }
+}
+
+/// The relevant blockers for a slider, ie. the rays without their last square
+fn make_magic_mask(x: i8, y: i8, directions: &[(i8, i8)]) -> Bitboard {
+    let mut result = Bitboard(0);
+    for (dx, dy) in directions.iter() {
+        let mut cx = x + dx;
+        let mut cy = y + dy;
+        while (0..8).contains(&(cx + dx)) && (0..8).contains(&(cy + dy)) {
+            result.slow_safe_set(cx, cy);
+            cx += dx;
+            cy += dy;
+        }
+    }
+    result
+}
+
+/// Steps along each direction until a blocker, which is included
+fn slow_slider_attacks(x: i8, y: i8, directions: &[(i8, i8)], occupancy: &Bitboard) -> Bitboard {
+    let mut result = Bitboard(0);
+    for (dx, dy) in directions.iter() {
+        let mut cx = x + dx;
+        let mut cy = y + dy;
+        while result.slow_safe_set(cx, cy) && !occupancy.is_set(cx as u8, cy as u8) {
+            cx += dx;
+            cy += dy;
+        }
+    }
+    result
 }
 
 fn make_line(y: u8) -> Bitboard {
@@ -167,6 +310,71 @@ mod test {
 
     use super::*;
 
+    /// xorshift64*, https://www.chessprogramming.org/Looking_for_Magics
+    struct MagicRng(u64);
+
+    impl MagicRng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(2685821657736338717)
+        }
+
+        /// Magics with few bits set are found faster
+        fn next_sparse(&mut self) -> u64 {
+            self.next() & self.next() & self.next()
+        }
+    }
+
+    /// Trial and error until every occupancy maps to an index without a conflicting collision
+    fn find_magic(x: i8, y: i8, directions: &[(i8, i8)], rng: &mut MagicRng) -> u64 {
+        let mask = make_magic_mask(x, y, directions);
+        let shift = 64 - mask.pop_count();
+        let mut occupancies = Vec::new();
+        for_each_subset(&mask, |occupancy| occupancies.push((occupancy, slow_slider_attacks(x, y, directions, &occupancy))));
+
+        let mut table = vec![None; 1 << mask.pop_count()];
+        loop {
+            let candidate = rng.next_sparse();
+            if (mask.0.wrapping_mul(candidate) >> 56).count_ones() < 6 {
+                continue;
+            }
+
+            table.fill(None);
+            let is_valid = occupancies.iter().all(|(occupancy, attacks)| {
+                let i = (occupancy.0.wrapping_mul(candidate) >> shift) as usize;
+                *table[i].get_or_insert(*attacks) == *attacks
+            });
+            if is_valid { return candidate; }
+        }
+    }
+
+    /// Prints new values for `ROOK_MAGICS` and `BISHOP_MAGICS`
+    #[ignore]
+    #[test]
+    fn find_magics() {
+        let mut rng = MagicRng(0x9e3779b97f4a7c15);
+        for directions in [ROOK_DIRECTIONS, BISHOP_DIRECTIONS].iter() {
+            let magics: Vec<String> = (0..64).map(|i| format!("0x{:016x}", find_magic(i % 8, i / 8, directions, &mut rng))).collect();
+            println!("{}\n", magics.join(", "));
+        }
+    }
+
+    #[test]
+    fn magic_attacks_match_rays() {
+        let presets = BitboardPresets::new();
+        let mut rng = MagicRng(1);
+        for _ in 0..200 {
+            let occupancy = Bitboard(rng.next() & rng.next());
+            for index in 0..64 {
+                let (x, y) = ((index % 8) as i8, (index / 8) as i8);
+                assert!(presets.rook_attacks(index, &occupancy) == slow_slider_attacks(x, y, &ROOK_DIRECTIONS, &occupancy));
+                assert!(presets.bishop_attacks(index, &occupancy) == slow_slider_attacks(x, y, &BISHOP_DIRECTIONS, &occupancy));
+            }
+        }
+    }
+
     #[ignore]
     #[test]
     fn eyeball_test() {
@@ -7,29 +7,41 @@ use super::super::*;
 use super::bitboard::*;
 
 pub enum RevertableMove {
-    /// (old squares, old hash to revert to, moved_castle_piece - first index is `Player` enum number, old king location)
-    NormalMove([BeforeSquare; 2], u64, [[bool; 2]; 2], Bitboard),
-    /// (oo/ooo, old hash to revert to, moved_castle_piece, old king location),
-    Castle(CastleType, u64, [bool; 2], Bitboard),
-    NoOp(u64)
+    /// (old squares, old hash to revert to, moved_castle_piece - first index is `Player` enum number, old king location, old en passant square, old halfmove clock)
+    NormalMove([BeforeSquare; 2], u64, [[bool; 2]; 2], Bitboard, Option<FastCoord>, u32),
+    /// (from, to, captured pawn location, old hash to revert to, old en passant square, old halfmove clock)
+    EnPassant(FastCoord, FastCoord, FastCoord, u64, Option<FastCoord>, u32),
+    /// (oo/ooo, old hash to revert to, moved_castle_piece, old king location, old en passant square, old halfmove clock),
+    Castle(CastleType, u64, [bool; 2], Bitboard, Option<FastCoord>, u32),
+    /// (old hash to revert to, old en passant square, old halfmove clock)
+    NoOp(u64, Option<FastCoord>, u32)
 }
 
 #[derive(Clone)]
 pub struct PlayerState {
     pub piece_locs: Bitboard,
+    /// Index: `Piece` enum number
+    pub piece_type_locs: [Bitboard; 6],
     pub king_location: Bitboard,
     pub is_castled: bool,
     /// Index: `CastleType` enum number
-    pub moved_castle_piece: [bool; 2]
+    pub moved_castle_piece: [bool; 2],
+    /// Start file of the king, only differs from standard chess in Chess960
+    pub king_start_x: u8,
+    /// Start files of the castling rooks. Index: `CastleType` enum number
+    pub rook_start_x: [u8; 2]
 }
 
 impl PlayerState {
     fn new() -> Self {
         Self {
             piece_locs: Bitboard(0),
+            piece_type_locs: [Bitboard(0); 6],
             king_location: Bitboard(0),
             moved_castle_piece: [false, false],
-            is_castled: false
+            is_castled: false,
+            king_start_x: STANDARD_KING_X,
+            rook_start_x: STANDARD_ROOK_X
         }
     }
 }
@@ -39,7 +51,15 @@ pub struct Board {
     player_with_turn: Player,
     d: [Square; 64],
     hash: u64,
-    player_state: [PlayerState; 2]
+    player_state: [PlayerState; 2],
+    /// The square skipped over by a pawn which just moved 2 squares, ie. where an en passant capture would land
+    en_passant: Option<FastCoord>,
+    /// Half moves since the last capture or pawn move
+    halfmove_clock: u32,
+    /// Starts at 1, incremented after black moves
+    fullmove_number: u32,
+    /// Hashes of all positions before the current one, pushed by `handle_move` and popped by `revert_move`
+    hash_history: Vec<u64>
 }
 
 impl Display for Board {
@@ -54,6 +74,87 @@ impl Display for Board {
     }
 }
 
+/// Checks and pins against the current player's king, so pseudo moves can be filtered without making them
+struct LegalMoveMasks {
+    king_index: u8,
+    checker_count: u8,
+    /// Squares which capture or block the checker. Every square if not in check, none if in double check
+    check_mask: Bitboard,
+    pinned: Bitboard,
+    /// Only set for pinned pieces. Squares from the king exclusive, up to the pinner inclusive
+    pin_rays: [Bitboard; 64]
+}
+
+/// Index of the blocker closest to the ray's origin. Precondition: `blockers` is non-empty and on a ray in `direction`
+#[inline]
+fn get_first_blocker_index(direction: usize, blockers: &Bitboard) -> u8 {
+    // The first 4 directions go towards index 0
+    if direction < 4 { blockers._lsb_to_index() } else { blockers._msb_to_index() }
+}
+
+/// Squares from `from` exclusive, up to `to` inclusive. Empty if they are not on the same row, column or diagonal
+fn get_ray_between(from: u8, to: u8) -> Bitboard {
+    for direction in 0..8 {
+        let ray = BITBOARD_PRESETS.rays[direction][from as usize];
+        if ray.is_set_index(to) {
+            return Bitboard(ray.0 ^ BITBOARD_PRESETS.rays[direction][to as usize].0);
+        }
+    }
+    Bitboard(0)
+}
+
+/// Pieces in `candidates` which are the only piece between the king at `king_index` and one of `sliders` (indexed as in `Board::get_sliders`).
+/// With the king's own pieces, these are pinned. With the opponent's pieces, moving them off the ray gives a discovered check.
+/// `pin_rays` gets the squares from the king exclusive, up to the slider inclusive, for each piece found.
+fn find_pins(king_index: u8, candidates: &Bitboard, sliders: &[Bitboard; 2], occupancy: &Bitboard, pin_rays: &mut [Bitboard; 64]) -> Bitboard {
+    let mut pinned = Bitboard(0);
+    for direction in 0..8 {
+        let ray = BITBOARD_PRESETS.rays[direction][king_index as usize];
+        let blockers = Bitboard(ray.0 & occupancy.0);
+        if blockers.0 == 0 {
+            continue;
+        }
+
+        let first = get_first_blocker_index(direction, &blockers);
+        if !candidates.is_set_index(first) {
+            continue;
+        }
+        let beyond = Bitboard(BITBOARD_PRESETS.rays[direction][first as usize].0 & occupancy.0);
+        if beyond.0 == 0 {
+            continue;
+        }
+        let second = get_first_blocker_index(direction, &beyond);
+        // Orthogonal directions are even
+        if sliders[direction % 2].is_set_index(second) {
+            pinned.set_index(first);
+            pin_rays[first as usize] = Bitboard(ray.0 ^ BITBOARD_PRESETS.rays[direction][second as usize].0);
+        }
+    }
+    pinned
+}
+
+fn get_post_castle_occupancy(castle_squares: &CastleSquares, occupancy: &Bitboard) -> Bitboard {
+    let mut result = *occupancy;
+    for BeforeAfterSquare(FastCoord(index), _, after) in castle_squares.sqs.iter() {
+        if let Square::Occupied(_, _) = after {
+            result.set_index(*index);
+        } else {
+            result.unset_index(*index);
+        }
+    }
+    result
+}
+
+/// Positions of the 2 knights among the 5 squares left after placing the bishops and queen, see `Board::new_chess960`
+static CHESS960_KNIGHTS: [(usize, usize); 10] = [
+    (0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)
+];
+
+fn place_on_nth_blank(row: &mut [Option<Piece>; 8], n: usize, piece: Piece) {
+    let x = (0..8).filter(|x| row[*x].is_none()).nth(n).unwrap();
+    row[x] = Some(piece);
+}
+
 /// Currently, manual board mutations (eg. set square) are only enabled for tests, no correctness guaranteed.
 impl Board {
     pub fn new() -> Self {
@@ -61,7 +162,11 @@ impl Board {
             d: [Square::Blank; 64],
             hash: 0,
             player_with_turn: Player::White,
-            player_state: [PlayerState::new(), PlayerState::new()]
+            player_state: [PlayerState::new(), PlayerState::new()],
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash_history: Vec::new()
         };
         board.set_standard_rows();
         board.get_player_state_mut(Player::White).king_location = Bitboard::from_index(CASTLE_UTILS.pre_castle_king_sq[Player::White as usize].0);
@@ -70,13 +175,67 @@ impl Board {
         board
     }
 
-    // TODO Can set up castle, but still disallow setting random squares
+    /// Chess960 start position using Scharnagl numbering, where 518 is the standard start position
+    pub fn new_chess960(index: u16) -> Self {
+        assert!(index < 960, "Chess960 position index must be below 960");
+
+        let mut row: [Option<Piece>; 8] = [None; 8];
+        let mut n = index as usize;
+        row[(n % 4) * 2 + 1] = Some(Piece::Bishop);
+        n /= 4;
+        row[(n % 4) * 2] = Some(Piece::Bishop);
+        n /= 4;
+        place_on_nth_blank(&mut row, n % 6, Piece::Queen);
+        n /= 6;
+        let (first_knight, second_knight) = CHESS960_KNIGHTS[n];
+        // Second knight first, so the first knight's blank square count is unaffected
+        place_on_nth_blank(&mut row, second_knight, Piece::Knight);
+        place_on_nth_blank(&mut row, first_knight, Piece::Knight);
+        place_on_nth_blank(&mut row, 0, Piece::Rook);
+        place_on_nth_blank(&mut row, 0, Piece::King);
+        place_on_nth_blank(&mut row, 0, Piece::Rook);
+
+        let mut board = Self::empty();
+        let mut king_x = 0;
+        let mut rook_xs = Vec::new();
+        for (x, piece) in row.iter().enumerate() {
+            let piece = piece.unwrap();
+            match piece {
+                Piece::King => king_x = x as u8,
+                Piece::Rook => rook_xs.push(x as u8),
+                _ => {}
+            };
+            for player in [Player::White, Player::Black].iter() {
+                board.set_by_xy(x as u8, player.first_row(), Square::Occupied(piece, *player));
+            }
+        }
+        board.set_uniform_row(2, Square::Occupied(Piece::Pawn, Player::White));
+        board.set_uniform_row(7, Square::Occupied(Piece::Pawn, Player::Black));
+
+        for player in [Player::White, Player::Black].iter() {
+            board.set_castle_files(*player, king_x, [rook_xs[1], rook_xs[0]]);
+        }
+        board.refresh_king_locations();
+        board.hash = board.calculate_hash();
+        board
+    }
+
+    /// Whether the king or castling rooks start on other files than in standard chess
+    pub fn is_chess960(&self) -> bool {
+        self.player_state.iter().any(|state| state.king_start_x != STANDARD_KING_X || state.rook_start_x != STANDARD_ROOK_X)
+    }
+
+    /// No pieces. Positions are set up through `PositionEditor` or `from_fen`, which validate them
     pub fn empty() -> Self {
         let mut board = Self {
             d: [Square::Blank; 64],
             hash: 0,
             player_with_turn: Player::White,
-            player_state: [PlayerState::new(), PlayerState::new()]
+            player_state: [PlayerState::new(), PlayerState::new()],
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash_history: Vec::new()
         };
         board
     }
@@ -94,6 +253,10 @@ impl Board {
                 // the square will stringify to eg. k, K, p, P, then it becomes eg. Ke2
                 format!("{}{} m={}", square, _to_coord, m.eval())
             },
+            MoveDescription::Promotion(_from_coord, _to_coord, promote_to) => {
+                let square = self.get_by_index(_from_coord.value());
+                format!("{}{}={} m={}", square, _to_coord, promote_to, m.eval())
+            },
             MoveDescription::Castle(castle_type) => {
                 if *castle_type == CastleType::Oo {
                     format!("oo m={}", m.eval())
@@ -155,6 +318,10 @@ impl Board {
             h ^= RANDOM_NUMBER_KEYS.moved_castle_piece[CastleType::Ooo as usize][Player::Black as usize]; 
         } 
 
+        if let Some(ep) = self.en_passant {
+            h ^= RANDOM_NUMBER_KEYS.en_passant_file[ep.to_coord().0 as usize];
+        }
+
         if self.get_player_with_turn() == Player::White { h ^= RANDOM_NUMBER_KEYS.is_white_to_play; }
         
         h
@@ -169,6 +336,22 @@ impl Board {
         assert_eq!(self.hash, self.calculate_hash());
     }
 
+    /// The piece bitboards must match the mailbox
+    #[cfg(test)]
+    pub fn assert_piece_locs(&self) {
+        let mut expected = [PlayerState::new(), PlayerState::new()];
+        for (i, sq) in self.d.iter().enumerate() {
+            if let Square::Occupied(piece, player) = sq {
+                expected[*player as usize].piece_locs.set_index(i as u8);
+                expected[*player as usize].piece_type_locs[*piece as usize].set_index(i as u8);
+            }
+        }
+        for (state, expected_state) in self.player_state.iter().zip(expected.iter()) {
+            assert!(state.piece_locs == expected_state.piece_locs);
+            assert!(state.piece_type_locs == expected_state.piece_type_locs);
+        }
+    }
+
     //////////////////////////////////////////////////
     // Player state
 
@@ -187,6 +370,150 @@ impl Board {
         &mut self.player_state[player as usize]
     }
 
+    //////////////////////////////////////////////////
+    // Position setup, hash and king locations must be refreshed after
+
+    pub(super) fn set_player_with_turn(&mut self, player: Player) {
+        self.player_with_turn = player;
+    }
+
+    pub(super) fn set_moved_castle_piece(&mut self, player: Player, castle_type: CastleType, moved: bool) {
+        self.get_player_state_mut(player).moved_castle_piece[castle_type as usize] = moved;
+    }
+
+    pub(super) fn set_castle_files(&mut self, player: Player, king_start_x: u8, rook_start_x: [u8; 2]) {
+        let player_state = self.get_player_state_mut(player);
+        player_state.king_start_x = king_start_x;
+        player_state.rook_start_x = rook_start_x;
+    }
+
+    pub(super) fn set_move_counters(&mut self, halfmove_clock: u32, fullmove_number: u32) {
+        self.halfmove_clock = halfmove_clock;
+        self.fullmove_number = fullmove_number;
+    }
+
+    /// Precondition: Each player has exactly 1 king
+    pub(super) fn refresh_king_locations(&mut self) {
+        for i in 0..self.d.len() {
+            if let Square::Occupied(Piece::King, player) = self.d[i] {
+                self.get_player_state_mut(player).king_location = Bitboard::from_index(i as u8);
+            }
+        }
+    }
+
+    pub(super) fn refresh_hash(&mut self) {
+        self.hash = self.calculate_hash();
+    }
+
+    //////////////////////////////////////////////////
+    // Move counters
+
+    #[inline]
+    pub fn get_halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    #[inline]
+    pub fn get_fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    #[inline]
+    pub fn get_hash_history_len(&self) -> usize {
+        self.hash_history.len()
+    }
+
+    /// Number of earlier occurrences of the current position, only looking back to the last capture or pawn move
+    pub fn get_repetition_count(&self) -> usize {
+        self.hash_history.iter().rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|hash| **hash == self.hash)
+            .count()
+    }
+
+    #[inline]
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.get_repetition_count() >= 2
+    }
+
+    /// Neither player can ever checkmate: only kings, a single minor piece, or any number of bishops all on the same square colour
+    pub fn is_insufficient_material(&self) -> bool {
+        let mut minor_count = 0;
+        let mut has_knight = false;
+        let mut bishop_square_colours = [false; 2];
+
+        let mut all_pieces = Bitboard(self.player_state[0].piece_locs.0 | self.player_state[1].piece_locs.0);
+        let has_mating_material = all_pieces.consume_loop_indices2(|index| {
+            match self.get_by_index(index) {
+                Square::Occupied(Piece::King, _) => false,
+                Square::Occupied(Piece::Knight, _) => {
+                    minor_count += 1;
+                    has_knight = true;
+                    false
+                },
+                Square::Occupied(Piece::Bishop, _) => {
+                    minor_count += 1;
+                    bishop_square_colours[((index % 8 + index / 8) % 2) as usize] = true;
+                    false
+                },
+                _ => true
+            }
+        });
+
+        if has_mating_material {
+            false
+        } else if minor_count <= 1 {
+            true
+        } else {
+            !(has_knight || bishop_square_colours[0] && bishop_square_colours[1])
+        }
+    }
+
+    /// 50 moves by each player without captures or pawn moves. Does not check whether the last move was checkmate.
+    #[inline]
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    //////////////////////////////////////////////////
+    // En passant
+
+    #[inline]
+    pub fn get_en_passant(&self) -> Option<FastCoord> {
+        self.en_passant
+    }
+
+    /// Whether `player` has a pawn beside `pawn`, which just moved two squares.
+    /// Otherwise the en passant square can't be used, and isn't part of the position
+    pub(super) fn has_en_passant_capturer(&self, pawn: Coord, player: Player) -> bool {
+        [pawn.0 as i32 - 1, pawn.0 as i32 + 1].iter().any(|x| {
+            matches!(self.get_by_xy_safe(*x, pawn.1 as i32), Ok(Square::Occupied(Piece::Pawn, p)) if *p == player)
+        })
+    }
+
+    pub(super) fn set_en_passant(&mut self, en_passant: Option<FastCoord>) {
+        if let Some(old) = self.en_passant {
+            self.hash ^= RANDOM_NUMBER_KEYS.en_passant_file[old.to_coord().0 as usize];
+        }
+        if let Some(new) = en_passant {
+            self.hash ^= RANDOM_NUMBER_KEYS.en_passant_file[new.to_coord().0 as usize];
+        }
+        self.en_passant = en_passant;
+    }
+
+    pub fn is_en_passant_capture(&self, m: &MoveWithEval) -> bool {
+        if let MoveDescription::NormalMove(_from_coord, _to_coord) = m.description() {
+            if Some(*_to_coord) == self.en_passant {
+                if let Square::Occupied(Piece::Pawn, _) = self.get_by_index(_from_coord.value()) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     //////////////////////////////////////////////////
     // Get set squares
 
@@ -225,27 +552,35 @@ impl Board {
         self.set_by_index(y * 8 + x, s);
     }
 
-    fn set_by_index_no_hash(&mut self, index: u8, s: Square) {
-        self.get_player_state_mut(Player::White).piece_locs.unset_index(index);
-        self.get_player_state_mut(Player::Black).piece_locs.unset_index(index);
-        if let Square::Occupied(_, new_player) = s {
-            self.get_player_state_mut(new_player).piece_locs.set_index(index);
+    /// Moves the square's bit between the piece bitboards, the mailbox is unchanged
+    #[inline]
+    fn update_piece_locs(&mut self, index: u8, s: Square) {
+        if let Square::Occupied(replaced_piece, replaced_piece_player) = self.d[index as usize] {
+            let state = self.get_player_state_mut(replaced_piece_player);
+            state.piece_locs.unset_index(index);
+            state.piece_type_locs[replaced_piece as usize].unset_index(index);
+        }
+        if let Square::Occupied(new_piece, new_player) = s {
+            let state = self.get_player_state_mut(new_player);
+            state.piece_locs.set_index(index);
+            state.piece_type_locs[new_piece as usize].set_index(index);
         }
+    }
+
+    fn set_by_index_no_hash(&mut self, index: u8, s: Square) {
+        self.update_piece_locs(index, s);
         self.d[index as usize] = s;
     }
 
     pub fn set_by_index(&mut self, index: u8, s: Square) {
-        self.get_player_state_mut(Player::White).piece_locs.unset_index(index);
-        self.get_player_state_mut(Player::Black).piece_locs.unset_index(index);
-
         if let Square::Occupied(replaced_piece, replaced_piece_player) = self.get_by_index(index) {
             self.hash ^= Self::get_square_hash(index as usize, *replaced_piece, *replaced_piece_player);
         }
         if let Square::Occupied(new_piece, new_player) = s {
             self.hash ^= Self::get_square_hash(index as usize, new_piece, new_player);
-            self.get_player_state_mut(new_player).piece_locs.set_index(index);
         }
 
+        self.update_piece_locs(index, s);
         self.d[index as usize] = s;
     }
 
@@ -268,7 +603,7 @@ impl Board {
     pub fn revert_move(&mut self, m: &RevertableMove) {
         let opponent = self.get_player_with_turn().other_player();
         match m {
-            RevertableMove::NormalMove(snapshot, old_hash, old_moved_castle_piece, old_king_location) => {
+            RevertableMove::NormalMove(snapshot, old_hash, old_moved_castle_piece, old_king_location, old_en_passant, old_halfmove_clock) => {
                 for BeforeSquare(fast_coord, square) in snapshot.iter() {
                     self.set_by_index_no_hash(fast_coord.0, *square);
                 }
@@ -280,13 +615,20 @@ impl Board {
                 opponent_state.king_location = *old_king_location;
 
                 self.hash = *old_hash;
+                self.en_passant = *old_en_passant;
+                self.halfmove_clock = *old_halfmove_clock;
             },
-            RevertableMove::Castle(castle_type, old_hash, old_moved_castle_piece, old_king_location) => {
-                let sqs: &[BeforeAfterSquare] = if *castle_type == CastleType::Oo {
-                    &CASTLE_UTILS.oo_sqs[opponent as usize]
-                } else {
-                    &CASTLE_UTILS.ooo_sqs[opponent as usize]
-                };
+            RevertableMove::EnPassant(from, to, captured, old_hash, old_en_passant, old_halfmove_clock) => {
+                self.set_by_index_no_hash(from.0, Square::Occupied(Piece::Pawn, opponent));
+                self.set_by_index_no_hash(to.0, Square::Blank);
+                self.set_by_index_no_hash(captured.0, Square::Occupied(Piece::Pawn, opponent.other_player()));
+
+                self.hash = *old_hash;
+                self.en_passant = *old_en_passant;
+                self.halfmove_clock = *old_halfmove_clock;
+            },
+            RevertableMove::Castle(castle_type, old_hash, old_moved_castle_piece, old_king_location, old_en_passant, old_halfmove_clock) => {
+                let sqs = &self.get_castle_squares(opponent, *castle_type).sqs;
                 self.apply_before_after_sqs(sqs, false);
 
                 let opponent_state = self.get_player_state_mut(opponent);
@@ -295,32 +637,58 @@ impl Board {
                 opponent_state.is_castled = false;
 
                 self.hash = *old_hash;
+                self.en_passant = *old_en_passant;
+                self.halfmove_clock = *old_halfmove_clock;
             }
-            RevertableMove::NoOp(old_hash) => {
+            RevertableMove::NoOp(old_hash, old_en_passant, old_halfmove_clock) => {
                 self.hash = *old_hash;
+                self.en_passant = *old_en_passant;
+                self.halfmove_clock = *old_halfmove_clock;
             }
         }
+        if opponent == Player::Black {
+            self.fullmove_number -= 1;
+        }
+        self.hash_history.pop();
         self.player_with_turn = opponent;
     }
 
     pub fn is_capture(&self, m: &MoveWithEval) -> bool {
-        if let MoveDescription::NormalMove(_, _to_coord) = m.description() {
+        if let MoveDescription::NormalMove(_, _to_coord) | MoveDescription::Promotion(_, _to_coord, _) = m.description() {
             if let Square::Occupied(_, _) = self.get_by_index(_to_coord.value()) {
                 return true;
             }
         }
+        self.is_en_passant_capture(m)
+    }
+
+    pub fn is_pawn_move(&self, m: &MoveWithEval) -> bool {
+        if let MoveDescription::NormalMove(_from_coord, _) | MoveDescription::Promotion(_from_coord, _, _) = m.description() {
+            if let Square::Occupied(Piece::Pawn, _) = self.get_by_index(_from_coord.value()) {
+                return true;
+            }
+        }
         false
     }
 
     #[inline]
     fn update_castle_for_piece(&mut self, player: Player, dragged_or_taken: Piece, origin_coord: &Coord) {
+        let is_start_row = origin_coord.1 == player.first_row();
+        let rook_start_x = self.get_player_state(player).rook_start_x;
         self.update_castle_state_hash(
             player, 
-            dragged_or_taken == Piece::King || (dragged_or_taken == Piece::Rook && origin_coord.0 == 7),
-            dragged_or_taken == Piece::King || (dragged_or_taken == Piece::Rook && origin_coord.0 == 0)
+            dragged_or_taken == Piece::King || (dragged_or_taken == Piece::Rook && is_start_row && origin_coord.0 == rook_start_x[CastleType::Oo as usize]),
+            dragged_or_taken == Piece::King || (dragged_or_taken == Piece::Rook && is_start_row && origin_coord.0 == rook_start_x[CastleType::Ooo as usize])
         );
     }
 
+    /// Castle squares for the player's king and rook start files
+    #[inline]
+    pub fn get_castle_squares(&self, player: Player, castle_type: CastleType) -> &'static CastleSquares {
+        let player_state = self.get_player_state(player);
+        CASTLE_UTILS.get(castle_type, player, player_state.king_start_x, player_state.rook_start_x[castle_type as usize])
+    }
+
     /// Only turns on, can't turn off
     fn update_castle_state_hash(&mut self, player: Player, moved_oo: bool, moved_ooo: bool) {
         let player_num = player as usize;
@@ -347,8 +715,26 @@ impl Board {
     /// All correctness checks will be move generation's responsibility.
     pub fn handle_move(&mut self, m: &MoveWithEval) -> RevertableMove {
         let old_hash = self.hash;
+        let old_en_passant = self.en_passant;
+        let old_halfmove_clock = self.halfmove_clock;
+        let resets_halfmove_clock = self.is_capture(m) || self.is_pawn_move(m);
+        self.hash_history.push(old_hash);
+
         let result = match m.description() {
-            MoveDescription::NormalMove(_from_coord, _to_coord) => {
+            MoveDescription::NormalMove(_from_coord, _to_coord) if self.is_en_passant_capture(m) => {
+                let curr_player = self.get_player_with_turn();
+                let to_coord = _to_coord.to_coord();
+                // The captured pawn is beside the moving pawn, ie. same row as the origin, same column as the destination
+                let captured = FastCoord::from_xy(to_coord.0, _from_coord.to_coord().1);
+
+                self.set_by_index(_from_coord.0, Square::Blank);
+                self.set_by_index(captured.0, Square::Blank);
+                self.set_by_index(_to_coord.0, Square::Occupied(Piece::Pawn, curr_player));
+                self.set_en_passant(None);
+
+                RevertableMove::EnPassant(*_from_coord, *_to_coord, captured, old_hash, old_en_passant, old_halfmove_clock)
+            }
+            MoveDescription::NormalMove(_from_coord, _to_coord) | MoveDescription::Promotion(_from_coord, _to_coord, _) => {
 
                 let from_sq_copy = *self.get_by_index(_from_coord.value());
                 let to_sq_copy = *self.get_by_index(_to_coord.value());
@@ -359,7 +745,9 @@ impl Board {
                         [BeforeSquare(*_from_coord, from_sq_copy), BeforeSquare(*_to_coord, to_sq_copy)], 
                         old_hash,
                         [self.get_player_state(Player::White).moved_castle_piece, self.get_player_state(Player::Black).moved_castle_piece],
-                        curr_player_state.king_location
+                        curr_player_state.king_location,
+                        old_en_passant,
+                        old_halfmove_clock
                     )
                 };
 
@@ -379,9 +767,8 @@ impl Board {
 
                     {
                         self.set_by_index(_from_coord.0, Square::Blank);
-                        if dragged_piece == Piece::Pawn && to_coord.1 == dragged_piece_player.last_row() {
-                            // TODO Add a method which configures preferred piece
-                            self.set_by_index(_to_coord.0, Square::Occupied(Piece::Queen, dragged_piece_player));
+                        if let MoveDescription::Promotion(_, _, promote_to) = m.description() {
+                            self.set_by_index(_to_coord.0, Square::Occupied(*promote_to, dragged_piece_player));
                         } else {
                             self.set_by_index(_to_coord.0, from_sq_copy);
                         }
@@ -389,6 +776,12 @@ impl Board {
                         if dragged_piece == Piece::King {
                             self.get_player_state_mut(curr_player).king_location = Bitboard::from_index(_to_coord.0);
                         }
+
+                        if dragged_piece == Piece::Pawn && (from_coord.1 as i32 - to_coord.1 as i32).abs() == 2 && self.has_en_passant_capturer(to_coord, opponent) {
+                            self.set_en_passant(Some(FastCoord::from_xy(from_coord.0, (from_coord.1 + to_coord.1) / 2)));
+                        } else {
+                            self.set_en_passant(None);
+                        }
                     }
                 } else {
                     console_error!("{}", self);
@@ -401,156 +794,321 @@ impl Board {
             MoveDescription::Castle(castle_type) => {
 
                 let curr_player = self.get_player_with_turn();
-                let curr_player_num = curr_player as usize;
                 let result = {
                     let curr_player_state = self.get_player_state(curr_player);
-                    RevertableMove::Castle(*castle_type, old_hash, curr_player_state.moved_castle_piece, curr_player_state.king_location)
+                    RevertableMove::Castle(*castle_type, old_hash, curr_player_state.moved_castle_piece, curr_player_state.king_location, old_en_passant, old_halfmove_clock)
                 };
 
-                let sqs: &[BeforeAfterSquare];
-                if *castle_type == CastleType::Oo {
-                    sqs = &CASTLE_UTILS.oo_sqs[curr_player_num];
-                } else {
-                    sqs = &CASTLE_UTILS.ooo_sqs[curr_player_num];
-                }
-                self.apply_before_after_sqs(sqs, true);
+                let castle_squares = self.get_castle_squares(curr_player, *castle_type);
+                self.apply_before_after_sqs(&castle_squares.sqs, true);
                 // We moved the king, so we moved a castle piece for both castles, set both flags
                 self.update_castle_state_hash(curr_player, true, true);
 
                 let curr_state = self.get_player_state_mut(curr_player);
                 curr_state.is_castled = true; // Does not need to be part of hash, but is useful to AI
-                curr_state.king_location = Bitboard::from_index(CASTLE_UTILS.post_castle_king_sq[*castle_type as usize][curr_player_num].0);
+                curr_state.king_location = Bitboard::from_index(castle_squares.post_castle_king_sq.0);
+                self.set_en_passant(None);
 
                 result
             }
             _ => {
-                RevertableMove::NoOp(old_hash)
+                self.set_en_passant(None);
+                RevertableMove::NoOp(old_hash, old_en_passant, old_halfmove_clock)
             }
         };
 
+        if resets_halfmove_clock {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.player_with_turn == Player::Black {
+            self.fullmove_number += 1;
+        }
+
         self.hash ^= RANDOM_NUMBER_KEYS.is_white_to_play;
         self.player_with_turn = self.player_with_turn.other_player();
 
         result
     }
 
-    /// Does not check if a castle piece has moved
-    fn _can_castle(&mut self, blank_coords: &[FastCoord], king_traversal_coords: &[FastCoord], curr_player: Player) -> bool {
+    /// Precondition: the current player is not in check. Does not check if a castle piece has moved.
+    fn _can_castle(&self, castle_type: CastleType, curr_player: Player) -> bool {
         let opponent = curr_player.other_player();
+        let castle_squares = self.get_castle_squares(curr_player, castle_type);
 
-        for FastCoord(index) in blank_coords.iter() {
+        if *self.get_by_index(castle_squares.rook_sq.0) != Square::Occupied(Piece::Rook, curr_player) {
+            return false;
+        }
+        for FastCoord(index) in castle_squares.blank_coords.iter() {
             if let Square::Occupied(_, _) = self.get_by_index(*index) {
                 return false;
             }
         }
 
-        let old_king_loc = {
-            let curr_state = self.get_player_state_mut(curr_player);
-            let _old_king_loc = curr_state.king_location;
-            for FastCoord(index) in king_traversal_coords.iter() {
-                curr_state.king_location.set_index(*index);
+        let occupancy = self.get_occupancy();
+        for FastCoord(index) in castle_squares.king_traversal_coords.iter() {
+            if self.is_attacked_by(*index, opponent, &occupancy) {
+                return false;
             }
-            _old_king_loc
-        };
-        let can_castle = !self.is_checking(opponent);
-        self.get_player_state_mut(curr_player).king_location = old_king_loc;
-        can_castle
-    }
-
-    fn try_write_castle(&mut self, curr_player: Player, castle_type: CastleType, move_list: &mut MoveList) {
-        if !self.get_player_state(curr_player).moved_castle_piece[castle_type as usize] {
-            let curr_player_num = curr_player as usize;
+        }
 
-            let blank_coords: &[FastCoord] = if castle_type == CastleType::Oo {
-                &CASTLE_UTILS.oo_blank_coords[curr_player_num]
-            } else {
-                &CASTLE_UTILS.ooo_blank_coords[curr_player_num]
-            };
+        // In Chess960, the castling rook can be what was blocking an attack on the king's destination
+        let after_occupancy = get_post_castle_occupancy(castle_squares, &occupancy);
+        !self.is_attacked_by(castle_squares.post_castle_king_sq.0, opponent, &after_occupancy)
+    }
 
-            if self._can_castle(blank_coords, &CASTLE_UTILS.king_traversal_coords[castle_type as usize][curr_player as usize], curr_player) {
-                move_list.write(MoveWithEval(MoveDescription::Castle(castle_type), 0));
+    /// Whether castling puts the opponent in check, either by the rook or by the king uncovering another slider
+    fn is_castle_checking(&self, castle_type: CastleType, curr_player: Player) -> bool {
+        let castle_squares = self.get_castle_squares(curr_player, castle_type);
+        let after_occupancy = get_post_castle_occupancy(castle_squares, &self.get_occupancy());
+        let [mut orthogonal_sliders, diagonal_sliders] = self.get_sliders(curr_player);
+        orthogonal_sliders.unset_index(castle_squares.rook_sq.0);
+        for BeforeAfterSquare(FastCoord(index), _, after) in castle_squares.sqs.iter() {
+            if let Square::Occupied(Piece::Rook, _) = after {
+                orthogonal_sliders.set_index(*index);
             }
         }
+
+        let opponent_king_index = self.get_player_state(curr_player.other_player()).king_location._lsb_to_index();
+        (BITBOARD_PRESETS.rook_attacks(opponent_king_index, &after_occupancy).0 & orthogonal_sliders.0) |
+            (BITBOARD_PRESETS.bishop_attacks(opponent_king_index, &after_occupancy).0 & diagonal_sliders.0) != 0
     }
 
-    /// Get moves for the current player
-    pub fn get_moves(&mut self, temp_moves: &mut MoveList, result: &mut MoveList) {
+    fn try_write_castle(&self, curr_player: Player, castle_type: CastleType, move_list: &mut MoveList) {
+        if !self.get_player_state(curr_player).moved_castle_piece[castle_type as usize] && self._can_castle(castle_type, curr_player) {
+            move_list.write(MoveWithEval(MoveDescription::Castle(castle_type), 0));
+        }
+    }
 
-        let curr_player = self.get_player_with_turn();
+    #[inline]
+    fn get_occupancy(&self) -> Bitboard {
+        Bitboard(self.player_state[0].piece_locs.0 | self.player_state[1].piece_locs.0)
+    }
 
-        temp_moves.write_index = 0;
-        self.get_pseudo_moves_for(curr_player, temp_moves);
- 
-        for i in 0..temp_moves.write_index {
-            let m = &temp_moves.v()[i];
-            let revertable = self.handle_move(m);
-            let is_checking = self.is_checking(self.get_player_with_turn());
-            self.revert_move(&revertable);
-            if !is_checking { result.write(m.clone()); }
-        }
+    #[inline]
+    pub fn get_piece_locs(&self, player: Player, piece: Piece) -> Bitboard {
+        self.get_player_state(player).piece_type_locs[piece as usize]
+    }
 
-        self.try_write_castle(curr_player, CastleType::Oo, result);
-        self.try_write_castle(curr_player, CastleType::Ooo, result);
+    /// Pieces of `player` which slide along rows and columns, or along diagonals. Index: 0 = orthogonal, 1 = diagonal
+    #[inline]
+    fn get_sliders(&self, player: Player) -> [Bitboard; 2] {
+        let queens = self.get_piece_locs(player, Piece::Queen).0;
+        [
+            Bitboard(self.get_piece_locs(player, Piece::Rook).0 | queens),
+            Bitboard(self.get_piece_locs(player, Piece::Bishop).0 | queens)
+        ]
     }
 
-    // TODO Attack/check via castle is missing
-    pub fn get_checks_captures_for(&mut self, player: Player, temp_moves: &mut MoveList, result: &mut MoveList) {
+    /// Whether `attacker` attacks the square at `index`, when the board has only the pieces in `occupancy`.
+    /// Attackers missing from `occupancy` are treated as captured.
+    fn is_attacked_by(&self, index: u8, attacker: Player, occupancy: &Bitboard) -> bool {
+        let i = index as usize;
+        let [orthogonal_sliders, diagonal_sliders] = self.get_sliders(attacker);
+
+        let attackers =
+            (BITBOARD_PRESETS.knight_jumps[i].0 & self.get_piece_locs(attacker, Piece::Knight).0) |
+            (BITBOARD_PRESETS.pawn_captures[attacker.other_player() as usize][i].0 & self.get_piece_locs(attacker, Piece::Pawn).0) |
+            (BITBOARD_PRESETS.king_moves[i].0 & self.get_piece_locs(attacker, Piece::King).0) |
+            (BITBOARD_PRESETS.rook_attacks(index, occupancy).0 & orthogonal_sliders.0) |
+            (BITBOARD_PRESETS.bishop_attacks(index, occupancy).0 & diagonal_sliders.0);
+        attackers & occupancy.0 != 0
+    }
 
+    /// Finds checks and pins against `player`'s king by scanning the 8 rays from the king
+    fn get_legal_move_masks(&self, player: Player) -> LegalMoveMasks {
         let opponent = player.other_player();
-        let curr_state = self.get_player_state(player);
+        let curr_locs = self.get_player_state(player).piece_locs;
+        let opponent_locs = self.get_player_state(opponent).piece_locs;
+        let occupancy = Bitboard(curr_locs.0 | opponent_locs.0);
+        let king_index = self.get_player_state(player).king_location._lsb_to_index();
+
+        let mut masks = LegalMoveMasks {
+            king_index,
+            checker_count: 0,
+            check_mask: Bitboard(0),
+            pinned: Bitboard(0),
+            pin_rays: [Bitboard(0); 64]
+        };
+
+        let opponent_sliders = self.get_sliders(opponent);
+        let mut slider_checkers = Bitboard(
+            (BITBOARD_PRESETS.rook_attacks(king_index, &occupancy).0 & opponent_sliders[0].0) |
+            (BITBOARD_PRESETS.bishop_attacks(king_index, &occupancy).0 & opponent_sliders[1].0)
+        );
+        slider_checkers.consume_loop_indices(|checker| {
+            masks.checker_count += 1;
+            masks.check_mask.0 |= get_ray_between(king_index, checker).0;
+        });
+        masks.pinned = find_pins(king_index, &curr_locs, &opponent_sliders, &occupancy, &mut masks.pin_rays);
+
+        let jumping_checkers = Bitboard(
+            (BITBOARD_PRESETS.knight_jumps[king_index as usize].0 & self.get_piece_locs(opponent, Piece::Knight).0) |
+            (BITBOARD_PRESETS.pawn_captures[player as usize][king_index as usize].0 & self.get_piece_locs(opponent, Piece::Pawn).0)
+        );
+        masks.checker_count += jumping_checkers.pop_count();
+        masks.check_mask.0 |= jumping_checkers.0;
+
+        if masks.checker_count == 0 {
+            masks.check_mask = Bitboard(!0);
+        } else if masks.checker_count >= 2 {
+            // Only king moves can escape a double check
+            masks.check_mask = Bitboard(0);
+        }
+        masks
+    }
+
+    /// Get legal moves for the current player
+    pub fn get_moves(&self, result: &mut MoveList) {
+
+        let curr_player = self.get_player_with_turn();
+        let opponent = curr_player.other_player();
+        let curr_state = self.get_player_state(curr_player);
         let opponent_state = self.get_player_state(opponent);
+        let masks = self.get_legal_move_masks(curr_player);
+
+        if masks.checker_count < 2 {
+            let mut piece_locs = Bitboard(curr_state.piece_locs.0 & !curr_state.king_location.0);
+            piece_locs.consume_loop_indices(|index| {
+                let origin = FastCoord(index);
+                let mut b = match self.get_by_index(index) {
+                    Square::Occupied(Piece::Pawn, Player::White) => _write_white_pawn_moves(origin, &curr_state.piece_locs, &opponent_state.piece_locs),
+                    Square::Occupied(Piece::Pawn, Player::Black) => _write_black_pawn_moves(origin, &curr_state.piece_locs, &opponent_state.piece_locs),
+                    Square::Occupied(Piece::Queen, _) => _write_queen_moves(origin, &curr_state.piece_locs, &opponent_state.piece_locs),
+                    Square::Occupied(Piece::Knight, _) => _write_knight_moves(origin, &curr_state.piece_locs),
+                    Square::Occupied(Piece::Bishop, _) => _write_bishop_moves(origin, &curr_state.piece_locs, &opponent_state.piece_locs),
+                    Square::Occupied(Piece::Rook, _) => _write_rook_moves(origin, &curr_state.piece_locs, &opponent_state.piece_locs),
+                    _ => Bitboard(0)
+                };
 
-        let opponent_king_coord = FastCoord(opponent_state.king_location._lsb_to_index());
-        let params = CheckCaptureParams {
-            curr_player_piece_locs: &curr_state.piece_locs,
-            opponent_piece_locs: &opponent_state.piece_locs,
-            king_potential_rook_atks: _write_rook_moves(opponent_king_coord, &curr_state.piece_locs, &opponent_state.piece_locs),
-            king_potential_bishop_atks: _write_bishop_moves(opponent_king_coord, &curr_state.piece_locs, &opponent_state.piece_locs),
-            king_potential_knight_atks: _write_knight_moves(opponent_king_coord, &curr_state.piece_locs),
-            king_potential_pawn_atks: BITBOARD_PRESETS.pawn_captures[opponent as usize][opponent_king_coord.0 as usize]
-        };
+                b.0 &= masks.check_mask.0;
+                if masks.pinned.is_set_index(index) {
+                    b.0 &= masks.pin_rays[index as usize].0;
+                }
+
+                if let Square::Occupied(Piece::Pawn, _) = self.get_by_index(index) {
+                    consume_pawn_to_move_list(&mut b, origin, curr_player, &PROMOTION_PIECES, result);
+                } else {
+                    consume_to_move_list(&mut b, origin, result);
+                }
+            });
+
+            self.write_en_passant_moves(curr_player, masks.king_index, result);
+        }
+
+        let king_moves = _write_king_moves(FastCoord(masks.king_index), &curr_state.piece_locs);
+        self.write_safe_king_moves(curr_player, masks.king_index, king_moves, result);
+
+        if masks.checker_count == 0 {
+            self.try_write_castle(curr_player, CastleType::Oo, result);
+            self.try_write_castle(curr_player, CastleType::Ooo, result);
+        }
+    }
 
-        temp_moves.write_index = 0;
-        let mut curr_piece_locs_clone = curr_state.piece_locs.clone();
-        curr_piece_locs_clone.consume_loop_indices(|index| {
-            self.get_checks_captures_at(FastCoord(index), &params, result);
+    /// Writes the king moves to `dests` which don't end up attacked
+    fn write_safe_king_moves(&self, curr_player: Player, king_index: u8, mut dests: Bitboard, result: &mut MoveList) {
+        let opponent = curr_player.other_player();
+        let mut occupancy_without_king = self.get_occupancy();
+        occupancy_without_king.unset_index(king_index);
+        dests.consume_loop_indices(|dest| {
+            if !self.is_attacked_by(dest, opponent, &occupancy_without_king) {
+                result.write(MoveWithEval(MoveDescription::NormalMove(FastCoord(king_index), FastCoord(dest)), 0));
+            }
         });
- 
-        for i in 0..temp_moves.write_index {
-            let m = &temp_moves.v()[i];
-            let revertable = self.handle_move(m);
-            let is_checking = self.is_checking(self.get_player_with_turn());
-            self.revert_move(&revertable);
-            if !is_checking { result.write(m.clone()); }
+    }
+
+    /// En passant removes 2 pieces from the same row, which pins don't cover, so check the king directly after the capture
+    fn write_en_passant_moves(&self, curr_player: Player, king_index: u8, result: &mut MoveList) {
+        if let Some(en_passant) = self.en_passant {
+            let opponent = curr_player.other_player();
+            let mut capturers = Bitboard(
+                BITBOARD_PRESETS.pawn_captures[opponent as usize][en_passant.0 as usize].0 & self.get_player_state(curr_player).piece_locs.0
+            );
+            capturers.consume_loop_indices(|from| {
+                if let Square::Occupied(Piece::Pawn, _) = self.get_by_index(from) {
+                    let captured = FastCoord::from_xy(en_passant.to_coord().0, FastCoord(from).to_coord().1);
+                    let mut occupancy = self.get_occupancy();
+                    occupancy.unset_index(from);
+                    occupancy.unset_index(captured.0);
+                    occupancy.set_index(en_passant.0);
+                    if !self.is_attacked_by(king_index, opponent, &occupancy) {
+                        result.write(MoveWithEval(MoveDescription::NormalMove(FastCoord(from), en_passant), 0));
+                    }
+                }
+            });
         }
     }
 
-    /// Precondition: `origin` piece is `player`'s piece
-    fn is_checking_at(&self, player: Player, origin: FastCoord) -> bool {
-        let state = self.get_player_state(player);
-        let opponent_state = self.get_player_state(player.other_player());
+    /// Legal captures, promotions and checks, for the quiescence search. Promotions are only to `TACTICAL_PROMOTION_PIECES`.
+    /// When in check, returns all legal moves instead, since any evasion may be forced.
+    pub fn get_checks_captures(&self, result: &mut MoveList) {
+        let curr_player = self.get_player_with_turn();
+        let masks = self.get_legal_move_masks(curr_player);
+        if masks.checker_count > 0 {
+            self.get_moves(result);
+            return;
+        }
 
-        match self.get_by_index(origin.0) {
-            Square::Occupied(Piece::Pawn, Player::White) => {
-                white_pawn_hits_king(origin, &state.piece_locs, &opponent_state.piece_locs, &opponent_state.king_location)
+        let opponent = curr_player.other_player();
+        let curr_state = self.get_player_state(curr_player);
+        let opponent_state = self.get_player_state(opponent);
+        let occupancy = self.get_occupancy();
+        let opponent_king_index = opponent_state.king_location._lsb_to_index();
+
+        // Squares each piece gives a direct check from. Index: `Piece` enum number
+        let rook_checks = BITBOARD_PRESETS.rook_attacks(opponent_king_index, &occupancy).0;
+        let bishop_checks = BITBOARD_PRESETS.bishop_attacks(opponent_king_index, &occupancy).0;
+        let checking_squares: [u64; 6] = [
+            BITBOARD_PRESETS.pawn_captures[opponent as usize][opponent_king_index as usize].0,
+            rook_checks,
+            BITBOARD_PRESETS.knight_jumps[opponent_king_index as usize].0,
+            bishop_checks,
+            rook_checks | bishop_checks,
+            0
+        ];
+        let mut discovery_rays = [Bitboard(0); 64];
+        let discoverers = find_pins(opponent_king_index, &curr_state.piece_locs, &self.get_sliders(curr_player), &occupancy, &mut discovery_rays);
+        // Moves which leave the ray of a discovered check
+        let get_discovered_checks = |index: u8| if discoverers.is_set_index(index) { !discovery_rays[index as usize].0 } else { 0 };
+
+        let mut piece_locs = Bitboard(curr_state.piece_locs.0 & !curr_state.king_location.0);
+        piece_locs.consume_loop_indices(|index| {
+            let origin = FastCoord(index);
+            if let Square::Occupied(piece, _) = self.get_by_index(index) {
+                let mut b = self.get_imaginary_pseudo_move_at(origin, *piece, curr_player);
+                if masks.pinned.is_set_index(index) {
+                    b.0 &= masks.pin_rays[index as usize].0;
+                }
+
+                if *piece == Piece::Pawn {
+                    b.0 &= opponent_state.piece_locs.0 | checking_squares[Piece::Pawn as usize] | get_discovered_checks(index) |
+                        BITBOARD_PRESETS.promotion_rows[curr_player as usize].0;
+                    consume_pawn_to_move_list(&mut b, origin, curr_player, &TACTICAL_PROMOTION_PIECES, result);
+                } else {
+                    b.0 &= opponent_state.piece_locs.0 | checking_squares[*piece as usize] | get_discovered_checks(index);
+                    consume_to_move_list(&mut b, origin, result);
+                }
             }
-            Square::Occupied(Piece::Pawn, Player::Black) => {
-                black_pawn_hits_king(origin, &state.piece_locs, &opponent_state.piece_locs, &opponent_state.king_location)
+        });
+        self.write_en_passant_moves(curr_player, masks.king_index, result);
+
+        let king_moves = Bitboard(
+            _write_king_moves(FastCoord(masks.king_index), &curr_state.piece_locs).0 &
+            (opponent_state.piece_locs.0 | get_discovered_checks(masks.king_index))
+        );
+        self.write_safe_king_moves(curr_player, masks.king_index, king_moves, result);
+
+        for castle_type in [CastleType::Oo, CastleType::Ooo].iter() {
+            if !curr_state.moved_castle_piece[*castle_type as usize] && self._can_castle(*castle_type, curr_player) && self.is_castle_checking(*castle_type, curr_player) {
+                result.write(MoveWithEval(MoveDescription::Castle(*castle_type), 0));
             }
-            Square::Occupied(Piece::Queen, _) => queen_hits_king(origin, &state.piece_locs, &opponent_state.piece_locs, &opponent_state.king_location),
-            Square::Occupied(Piece::Knight, _) => knight_hits_king(origin, &state.piece_locs, &opponent_state.king_location),
-            Square::Occupied(Piece::King, _) => king_hits_king(origin, &state.piece_locs, &opponent_state.king_location),
-            Square::Occupied(Piece::Bishop, _) => bishop_hits_king(origin, &state.piece_locs, &opponent_state.piece_locs, &opponent_state.king_location),
-            Square::Occupied(Piece::Rook, _) => rook_hits_king(origin, &state.piece_locs, &opponent_state.piece_locs, &opponent_state.king_location),
-            Square::Blank => false
         }
     }
 
     pub fn is_checking(&self, player: Player) -> bool {
-        let mut piece_locs_clone = self.get_player_state(player).piece_locs.clone();
-        piece_locs_clone.consume_loop_indices2(|index| {
-            self.is_checking_at(player, FastCoord(index))
-        })
+        let opponent_king_location = self.get_player_state(player.other_player()).king_location;
+        opponent_king_location.0 != 0 && self.is_attacked_by(opponent_king_location._lsb_to_index(), player, &self.get_occupancy())
     }
 
     pub fn rewrite_af_boards(&self, result: &mut AttackFromBoards) {
@@ -586,6 +1144,8 @@ impl Board {
         };
     }
 
+    /// For eyeballing move generation in tests, the engine only generates legal moves
+    #[cfg(test)]
     pub fn get_pseudo_moves_at(&self, origin: FastCoord, result: &mut MoveList) {
         if let Square::Occupied(_, player) = self.get_by_index(origin.0) {
             self._get_pseudo_moves_at(origin, *player, result);
@@ -593,22 +1153,28 @@ impl Board {
     }
 
     /// Precondition: `origin` piece is `player`'s piece
+    #[cfg(test)]
     fn _get_pseudo_moves_at(&self, origin: FastCoord, player: Player, result: &mut MoveList) {
         let curr_state = self.get_player_state(player);
         let opponent_state = self.get_player_state(player.other_player());
+        // Pawns can also capture onto the (empty) en passant square, which is only available to the player with the turn
+        let mut pawn_capturable_locs = Bitboard(opponent_state.piece_locs.0);
+        if let Some(en_passant) = self.en_passant.filter(|_| player == self.get_player_with_turn()) {
+            pawn_capturable_locs.set_index(en_passant.0);
+        }
 
         match self.get_by_index(origin.0) {
-            Square::Occupied(Piece::Pawn, Player::White) => {
-                write_white_pawn_moves(result, origin, &curr_state.piece_locs, &opponent_state.piece_locs);
-            }
-            Square::Occupied(Piece::Pawn, Player::Black) => {
-                write_black_pawn_moves(result, origin, &curr_state.piece_locs, &opponent_state.piece_locs);
-            }
-            Square::Occupied(Piece::Queen, _) => write_queen_moves(result, origin, &curr_state.piece_locs, &opponent_state.piece_locs),
-            Square::Occupied(Piece::Knight, _) => write_knight_moves(result, origin, &curr_state.piece_locs),
-            Square::Occupied(Piece::King, _) => write_king_moves(result, origin, &curr_state.piece_locs),
-            Square::Occupied(Piece::Bishop, _) => write_bishop_moves(result, origin, &curr_state.piece_locs, &opponent_state.piece_locs),
-            Square::Occupied(Piece::Rook, _) => write_rook_moves(result, origin, &curr_state.piece_locs, &opponent_state.piece_locs),
+            Square::Occupied(Piece::Pawn, _) => {
+                let mut b = match player {
+                    Player::White => _write_white_pawn_moves(origin, &curr_state.piece_locs, &pawn_capturable_locs),
+                    Player::Black => _write_black_pawn_moves(origin, &curr_state.piece_locs, &pawn_capturable_locs)
+                };
+                consume_pawn_to_move_list(&mut b, origin, player, &PROMOTION_PIECES, result);
+            },
+            Square::Occupied(piece, _) => {
+                let mut b = self.get_imaginary_pseudo_move_at(origin, *piece, player);
+                consume_to_move_list(&mut b, origin, result);
+            },
             Square::Blank => {}
         };
     }
@@ -633,31 +1199,6 @@ impl Board {
         }
     }
 
-    /// Precondition: `origin` piece is `params` current player's piece
-    fn get_checks_captures_at(&self, origin: FastCoord, params: &CheckCaptureParams, result: &mut MoveList) {
-        match self.get_by_index(origin.0) {
-            Square::Occupied(Piece::Pawn, Player::White) => {
-                write_white_pawn_ccs(result, origin, &params);
-            }
-            Square::Occupied(Piece::Pawn, Player::Black) => {
-                write_black_pawn_ccs(result, origin, &params);
-            }
-            Square::Occupied(Piece::Queen, _) => write_queen_ccs(result, origin, params),
-            Square::Occupied(Piece::Knight, _) => write_knight_ccs(result, origin, params),
-            Square::Occupied(Piece::King, _) => write_king_captures(result, origin, params.curr_player_piece_locs, params.opponent_piece_locs),
-            Square::Occupied(Piece::Bishop, _) => write_bishop_ccs(result, origin, params),
-            Square::Occupied(Piece::Rook, _) => write_rook_ccs(result, origin, params),
-            Square::Blank => {}
-        };
-    }
-
-    pub fn get_pseudo_moves_for(&self, player: Player, result: &mut MoveList) {
-        let mut piece_locs_clone = self.get_player_state(player).piece_locs.clone();
-        piece_locs_clone.consume_loop_indices(|index| {
-            self._get_pseudo_moves_at(FastCoord(index), player, result);
-        });
-    }
-
     //////////////////////////////////////////////////
     // Board setup
 
@@ -741,10 +1282,10 @@ mod test {
         board.set_by_file_rank_test('d', 3, Square::Occupied(Piece::Pawn, Player::Black));
         board.set_by_file_rank_test('f', 3, Square::Occupied(Piece::Pawn, Player::Black));
         board.set_by_file_rank_test('e', 3, Square::Occupied(Piece::Pawn, Player::Black));
+        board.player_with_turn = Player::Black;
 
-        let mut temp = MoveList::new(100);
         let mut result = MoveList::new(100);
-        board.get_checks_captures_for(Player::Black, &mut temp, &mut result);
+        board.get_checks_captures(&mut result);
 
         for m in result.v() {
             if let MoveDescription::NormalMove(_from, _to) = m.description() {
@@ -766,9 +1307,8 @@ mod test {
         board.get_player_state_mut(Player::White).king_location = Bitboard::from_index(FastCoord::from_xy(0, 7).0);
         board.get_player_state_mut(Player::Black).king_location = Bitboard::from_index(FastCoord::from_xy(4, 3).0);
 
-        let mut temp = MoveList::new(10);
         let mut result = MoveList::new(10);
-        board.get_checks_captures_for(Player::White, &mut temp, &mut result);
+        board.get_checks_captures(&mut result);
 
         for m in result.v() {
             if let MoveDescription::NormalMove(_from, _to) = m.description() {
@@ -779,4 +1319,399 @@ mod test {
             }
         }
     }
+
+    fn fr(file: char, rank: u8) -> FastCoord {
+        FastCoord::from_coord(&file_rank_to_xy(file, rank))
+    }
+
+    fn play(board: &mut Board, from: (char, u8), to: (char, u8)) -> RevertableMove {
+        board.handle_move(&MoveWithEval(MoveDescription::NormalMove(fr(from.0, from.1), fr(to.0, to.1)), 0))
+    }
+
+    fn has_move(board: &mut Board, from: (char, u8), to: (char, u8)) -> bool {
+        let mut result = MoveList::new(50);
+        board.get_moves(&mut result);
+        result.v()[0..result.write_index].iter().any(|m| {
+            if let MoveDescription::NormalMove(_from, _to) = m.description() {
+                *_from == fr(from.0, from.1) && *_to == fr(to.0, to.1)
+            } else {
+                false
+            }
+        })
+    }
+
+    #[test]
+    fn en_passant_capture_and_revert() {
+        let mut board = Board::new();
+        play(&mut board, ('e', 2), ('e', 4));
+        // No black pawn can capture on e3
+        assert!(board.get_en_passant().is_none());
+        play(&mut board, ('a', 7), ('a', 6));
+        assert!(board.get_en_passant().is_none());
+        play(&mut board, ('e', 4), ('e', 5));
+        play(&mut board, ('d', 7), ('d', 5));
+        assert!(board.get_en_passant() == Some(fr('d', 6)));
+        board.assert_hash();
+
+        assert!(has_move(&mut board, ('e', 5), ('d', 6)));
+        let hash_before = board.get_hash();
+        let revertable = play(&mut board, ('e', 5), ('d', 6));
+        board.assert_hash();
+        assert!(*board.get_by_file_rank_safe('d', 5).unwrap() == Square::Blank);
+        assert!(*board.get_by_file_rank_safe('e', 5).unwrap() == Square::Blank);
+        assert!(*board.get_by_file_rank_safe('d', 6).unwrap() == Square::Occupied(Piece::Pawn, Player::White));
+        assert!(!board.get_player_state(Player::Black).piece_locs.is_set(3, 3));
+
+        board.revert_move(&revertable);
+        board.assert_hash();
+        assert_eq!(board.get_hash(), hash_before);
+        assert!(board.get_en_passant() == Some(fr('d', 6)));
+        assert!(*board.get_by_file_rank_safe('d', 5).unwrap() == Square::Occupied(Piece::Pawn, Player::Black));
+        assert!(*board.get_by_file_rank_safe('e', 5).unwrap() == Square::Occupied(Piece::Pawn, Player::White));
+        assert!(*board.get_by_file_rank_safe('d', 6).unwrap() == Square::Blank);
+    }
+
+    #[test]
+    fn en_passant_expires() {
+        let mut board = Board::new();
+        play(&mut board, ('e', 2), ('e', 4));
+        play(&mut board, ('a', 7), ('a', 6));
+        play(&mut board, ('e', 4), ('e', 5));
+        play(&mut board, ('d', 7), ('d', 5));
+        play(&mut board, ('h', 2), ('h', 3));
+        play(&mut board, ('a', 6), ('a', 5));
+        assert!(!has_move(&mut board, ('e', 5), ('d', 6)));
+        board.assert_hash();
+    }
+
+    #[test]
+    fn piece_locs_follow_moves() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        board.assert_piece_locs();
+        let mut knights = Bitboard(0);
+        knights.set_index(fr('c', 3).0);
+        knights.set_index(fr('e', 5).0);
+        assert!(board.get_piece_locs(Player::White, Piece::Knight) == knights);
+
+        let mut result = MoveList::new(50);
+        board.get_moves(&mut result);
+        for m in result.v()[0..result.write_index].iter() {
+            let revertable = board.handle_move(m);
+            board.assert_piece_locs();
+            board.revert_move(&revertable);
+            board.assert_piece_locs();
+        }
+    }
+
+    #[test]
+    fn pins_and_checks() {
+        // The e-file pawn is pinned, but can still capture the pinner
+        let mut board = Board::from_fen("4k3/8/8/8/8/4r3/3PP3/4K3 w - - 0 1").unwrap();
+        assert!(!has_move(&mut board, ('e', 2), ('e', 4)));
+        assert!(has_move(&mut board, ('d', 2), ('e', 3)));
+
+        // En passant would expose the king along the row
+        let mut board = Board::from_fen("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1").unwrap();
+        assert!(!has_move(&mut board, ('e', 5), ('d', 6)));
+
+        // Only blocks, captures of the checker and king moves escape a check
+        let mut board = Board::from_fen("4k3/8/8/8/1b6/8/8/1N2K2R w K - 0 1").unwrap();
+        assert!(has_move(&mut board, ('b', 1), ('d', 2)));
+        assert!(!has_move(&mut board, ('b', 1), ('a', 3)));
+        assert!(has_move(&mut board, ('e', 1), ('f', 2)));
+        assert!(!has_castle(&mut board, CastleType::Oo));
+    }
+
+    fn promotion_board() -> Board {
+        let mut board = Board::empty();
+        board.set_by_file_rank_test('b', 7, Square::Occupied(Piece::Pawn, Player::White));
+        board.set_by_file_rank_test('a', 8, Square::Occupied(Piece::Rook, Player::Black));
+        board.set_by_file_rank_test('h', 1, Square::Occupied(Piece::King, Player::White));
+        board.set_by_file_rank_test('h', 8, Square::Occupied(Piece::King, Player::Black));
+        board.get_player_state_mut(Player::White).king_location = Bitboard::from_index(fr('h', 1).0);
+        board.get_player_state_mut(Player::Black).king_location = Bitboard::from_index(fr('h', 8).0);
+        board.hash = board.calculate_hash();
+        board
+    }
+
+    fn count_promotions(ml: &MoveList, from: FastCoord, to: FastCoord) -> usize {
+        ml.v()[0..ml.write_index].iter().filter(|m| {
+            if let MoveDescription::Promotion(_from, _to, _) = m.description() {
+                *_from == from && *_to == to
+            } else {
+                false
+            }
+        }).count()
+    }
+
+    #[test]
+    fn promotion_moves() {
+        let mut board = promotion_board();
+        let mut result = MoveList::new(50);
+        board.get_moves(&mut result);
+
+        assert_eq!(count_promotions(&result, fr('b', 7), fr('b', 8)), 4);
+        assert_eq!(count_promotions(&result, fr('b', 7), fr('a', 8)), 4);
+        assert!(!has_move(&mut board, ('b', 7), ('b', 8)));
+
+        result.write_index = 0;
+        board.get_checks_captures(&mut result);
+        assert_eq!(count_promotions(&result, fr('b', 7), fr('b', 8)), 2);
+        assert_eq!(count_promotions(&result, fr('b', 7), fr('a', 8)), 2);
+    }
+
+    fn get_move_descriptions(board: &Board, is_tactical: bool) -> Vec<MoveDescription> {
+        let mut result = MoveList::new(100);
+        if is_tactical {
+            board.get_checks_captures(&mut result);
+        } else {
+            board.get_moves(&mut result);
+        }
+        result.v()[0..result.write_index].iter().map(|m| m.description().clone()).collect()
+    }
+
+    #[test]
+    fn checks_captures_edge_cases() {
+        let normal = |from: (char, u8), to: (char, u8)| MoveDescription::NormalMove(fr(from.0, from.1), fr(to.0, to.1));
+
+        // The knight is pinned, so it can't capture
+        let board = Board::from_fen("4k3/8/8/4r3/3p4/8/4N3/4K3 w - - 0 1").unwrap();
+        assert!(!get_move_descriptions(&board, true).contains(&normal(('e', 2), ('d', 4))));
+
+        // Any knight move uncovers the bishop, the quiet rook move is not a check
+        let board = Board::from_fen("7k/8/8/8/3N4/8/1B6/K5R1 w - - 0 1").unwrap();
+        let moves = get_move_descriptions(&board, true);
+        assert!(moves.contains(&normal(('d', 4), ('b', 3))));
+        assert!(moves.contains(&normal(('g', 1), ('h', 1))));
+        assert!(!moves.contains(&normal(('g', 1), ('g', 2))));
+
+        // The rook checks after castling
+        let board = Board::from_fen("5k2/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert!(get_move_descriptions(&board, true).contains(&MoveDescription::Castle(CastleType::Oo)));
+        let board = Board::from_fen("6k1/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert!(!get_move_descriptions(&board, true).contains(&MoveDescription::Castle(CastleType::Oo)));
+
+        // Every evasion is tactical when in check
+        let board = Board::from_fen("4k3/8/8/8/1b6/8/8/1N2K2R w K - 0 1").unwrap();
+        assert_eq!(get_move_descriptions(&board, true).len(), get_move_descriptions(&board, false).len());
+    }
+
+    /// Compares against legal moves filtered by making them
+    #[test]
+    fn checks_captures_match_legal_moves() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10"
+        ];
+        for fen in fens.iter() {
+            let mut root = Board::from_fen(fen).unwrap();
+            let mut boards = vec![root.clone()];
+            for m in get_move_descriptions(&root, false).iter() {
+                let revertable = root.handle_move(&MoveWithEval(m.clone(), 0));
+                boards.push(root.clone());
+                root.revert_move(&revertable);
+            }
+
+            for board in boards.iter_mut() {
+                let is_in_check = board.is_checking(board.get_player_with_turn().other_player());
+                let tactical = get_move_descriptions(board, true);
+                let legal = get_move_descriptions(board, false);
+                assert!(tactical.iter().all(|m| legal.contains(m)), "{}", board.to_fen());
+                for m in legal.into_iter() {
+                    let m_with_eval = MoveWithEval(m.clone(), 0);
+                    let is_capture = board.is_capture(&m_with_eval);
+                    let revertable = board.handle_move(&m_with_eval);
+                    let is_check = board.is_checking(board.get_player_with_turn().other_player());
+                    board.revert_move(&revertable);
+
+                    let expected = match m {
+                        MoveDescription::Promotion(_, _, piece) => TACTICAL_PROMOTION_PIECES.contains(&piece) || is_in_check,
+                        _ => is_capture || is_check || is_in_check
+                    };
+                    assert_eq!(tactical.contains(&m), expected, "{} {}", board.to_fen(), board.stringify_move(&m_with_eval));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn underpromotion_and_revert() {
+        let mut board = promotion_board();
+        let hash_before = board.get_hash();
+
+        let revertable = board.handle_move(&MoveWithEval(MoveDescription::Promotion(fr('b', 7), fr('a', 8), Piece::Knight), 0));
+        board.assert_hash();
+        assert!(*board.get_by_file_rank_safe('a', 8).unwrap() == Square::Occupied(Piece::Knight, Player::White));
+        assert!(*board.get_by_file_rank_safe('b', 7).unwrap() == Square::Blank);
+
+        board.revert_move(&revertable);
+        assert_eq!(board.get_hash(), hash_before);
+        assert!(*board.get_by_file_rank_safe('a', 8).unwrap() == Square::Occupied(Piece::Rook, Player::Black));
+        assert!(*board.get_by_file_rank_safe('b', 7).unwrap() == Square::Occupied(Piece::Pawn, Player::White));
+    }
+
+    #[test]
+    fn move_counters_and_revert() {
+        let mut board = Board::new();
+
+        let e4 = play(&mut board, ('e', 2), ('e', 4));
+        assert_eq!((board.get_halfmove_clock(), board.get_fullmove_number()), (0, 1));
+        let nf6 = play(&mut board, ('g', 8), ('f', 6));
+        assert_eq!((board.get_halfmove_clock(), board.get_fullmove_number()), (1, 2));
+        let nf3 = play(&mut board, ('g', 1), ('f', 3));
+        assert_eq!((board.get_halfmove_clock(), board.get_fullmove_number()), (2, 2));
+        let nxe4 = play(&mut board, ('f', 6), ('e', 4));
+        assert_eq!((board.get_halfmove_clock(), board.get_fullmove_number()), (0, 3));
+
+        board.revert_move(&nxe4);
+        assert_eq!((board.get_halfmove_clock(), board.get_fullmove_number()), (2, 2));
+        board.revert_move(&nf3);
+        board.revert_move(&nf6);
+        board.revert_move(&e4);
+        assert_eq!((board.get_halfmove_clock(), board.get_fullmove_number()), (0, 1));
+    }
+
+    #[test]
+    fn fifty_move_draw() {
+        let mut board = Board::from_fen("8/8/4k3/8/8/4K3/8/R7 w - - 99 80").unwrap();
+        assert!(!board.is_fifty_move_draw());
+
+        let revertable = play(&mut board, ('a', 1), ('a', 2));
+        assert!(board.is_fifty_move_draw());
+        assert_eq!(board.get_fullmove_number(), 80);
+
+        board.revert_move(&revertable);
+        assert!(!board.is_fifty_move_draw());
+
+        play(&mut board, ('e', 3), ('e', 4));
+        play(&mut board, ('e', 6), ('e', 5));
+        assert_eq!(board.get_fullmove_number(), 81);
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut board = Board::new();
+        let mut revertables = Vec::new();
+        for _ in 0..2 {
+            assert!(!board.is_threefold_repetition());
+            revertables.push(play(&mut board, ('g', 1), ('f', 3)));
+            revertables.push(play(&mut board, ('g', 8), ('f', 6)));
+            revertables.push(play(&mut board, ('f', 3), ('g', 1)));
+            revertables.push(play(&mut board, ('f', 6), ('g', 8)));
+        }
+        assert_eq!(board.get_repetition_count(), 2);
+        assert!(board.is_threefold_repetition());
+
+        board.revert_move(&revertables.pop().unwrap());
+        assert_eq!(board.get_repetition_count(), 1);
+
+        // A pawn move makes earlier positions unreachable
+        let mut board = Board::new();
+        play(&mut board, ('g', 1), ('f', 3));
+        play(&mut board, ('g', 8), ('f', 6));
+        play(&mut board, ('e', 2), ('e', 3));
+        play(&mut board, ('f', 6), ('g', 8));
+        play(&mut board, ('e', 3), ('e', 4));
+        assert_eq!(board.get_repetition_count(), 0);
+
+        // A double pawn push nothing can capture en passant is repeated by the later positions
+        let mut board = Board::new();
+        play(&mut board, ('e', 2), ('e', 4));
+        for _ in 0..2 {
+            play(&mut board, ('g', 8), ('f', 6));
+            play(&mut board, ('g', 1), ('f', 3));
+            play(&mut board, ('f', 6), ('g', 8));
+            play(&mut board, ('f', 3), ('g', 1));
+        }
+        assert_eq!(board.get_repetition_count(), 2);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let draws = [
+            "8/8/4k3/8/8/4K3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/4K3/8/5B2 w - - 0 1",
+            "8/8/4k3/8/8/4K3/8/6n1 w - - 0 1",
+            "8/1b6/4k3/8/8/4K3/8/5B2 w - - 0 1",
+            "8/8/4k3/8/8/4K3/8/3B1B2 b - - 0 1"
+        ];
+        let not_draws = [
+            "8/8/4k3/8/8/4K3/4P3/8 w - - 0 1",
+            "8/8/4k3/8/8/4K3/8/R7 w - - 0 1",
+            "8/8/4k3/8/8/4K3/8/4BB2 w - - 0 1",
+            "8/8/4k3/8/8/4K3/8/4NB2 w - - 0 1",
+            "8/8/4k3/6n1/8/4K3/8/6N1 w - - 0 1",
+            "8/2b5/4k3/8/8/4K3/8/5B2 w - - 0 1"
+        ];
+        for fen in draws.iter() {
+            assert!(Board::from_fen(fen).unwrap().is_insufficient_material(), "{}", fen);
+        }
+        for fen in not_draws.iter() {
+            assert!(!Board::from_fen(fen).unwrap().is_insufficient_material(), "{}", fen);
+        }
+    }
+
+    fn has_castle(board: &mut Board, castle_type: CastleType) -> bool {
+        let mut result = MoveList::new(50);
+        board.get_moves(&mut result);
+        result.v()[0..result.write_index].iter().any(|m| {
+            if let MoveDescription::Castle(_castle_type) = m.description() {
+                *_castle_type == castle_type
+            } else {
+                false
+            }
+        })
+    }
+
+    #[test]
+    fn chess960_start_positions() {
+        let standard = Board::new_chess960(518);
+        assert_eq!(standard.to_fen(), Board::new().to_fen());
+        assert_eq!(standard.get_hash(), Board::new().get_hash());
+
+        assert_eq!(Board::new_chess960(0).to_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(Board::new_chess960(959).to_fen(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1");
+        assert!(!standard.is_chess960());
+        assert!(Board::new_chess960(0).is_chess960());
+    }
+
+    #[test]
+    fn chess960_castle() {
+        // Rook on d8 stops the king passing d1 for O-O, but O-O-O only needs the rook to cross it
+        let mut board = Board::from_fen("3rk3/8/8/8/8/8/8/RK4R1 w KQ - 0 1").unwrap();
+        assert!(!has_castle(&mut board, CastleType::Oo));
+        assert!(has_castle(&mut board, CastleType::Ooo));
+
+        let hash_before = board.get_hash();
+        let revertable = board.handle_move(&MoveWithEval(MoveDescription::Castle(CastleType::Ooo), 0));
+        board.assert_hash();
+        assert_eq!(board.to_fen(), "3rk3/8/8/8/8/8/8/2KR2R1 b - - 1 1");
+
+        board.revert_move(&revertable);
+        assert_eq!(board.get_hash(), hash_before);
+        assert_eq!(board.to_fen(), "3rk3/8/8/8/8/8/8/RK4R1 w KQ - 0 1");
+
+        // The king stays on c1, but the castling rook was blocking the a1 rook
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/rRK5 w Q - 0 1").unwrap();
+        assert!(!has_castle(&mut board, CastleType::Ooo));
+    }
+
+    #[test]
+    fn chess960_fen_castle_files() {
+        // The inner rook needs a file letter
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/1RK2R1R w FB - 0 1").unwrap();
+        assert_eq!(board.get_player_state(Player::White).rook_start_x, [5, 1]);
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/1RK2R1R w FQ - 0 1");
+
+        // Moving the castling rook loses its right, moving the other one doesn't
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/1RK2R1R w FQ - 0 1").unwrap();
+        play(&mut board, ('h', 1), ('h', 2));
+        assert_eq!(board.get_player_state(Player::White).moved_castle_piece, [false, false]);
+        play(&mut board, ('e', 8), ('e', 7));
+        play(&mut board, ('f', 1), ('f', 2));
+        assert_eq!(board.get_player_state(Player::White).moved_castle_piece, [true, false]);
+    }
 }
@@ -3,97 +3,124 @@ use super::entities::*;
 use super::coords::*;
 use super::super::*;
 
-/// Size 2 arrays are indexed by `Player` enum numbers.
-/// When not split by oo/ooo, first index: `CastleType` enum number, then `Player` enum number
-pub struct CastleUtils {
-    pub oo_sqs: [[BeforeAfterSquare; 4]; 2],
-    pub ooo_sqs: [[BeforeAfterSquare; 5]; 2],
-
-    pub oo_blank_coords: [[FastCoord; 2]; 2],
-    pub ooo_blank_coords: [[FastCoord; 3]; 2],
+/// Standard chess start files, which Chess960 positions may change
+pub static STANDARD_KING_X: u8 = 4;
+/// Index: `CastleType` enum number
+pub static STANDARD_ROOK_X: [u8; 2] = [7, 0];
 
-    pub king_traversal_coords: [[[FastCoord; 2]; 2]; 2],
-    pub draggable_coords: [[[(FastCoord, FastCoord); 3]; 2]; 2],
+/// Index: `CastleType` enum number
+static POST_CASTLE_KING_X: [u8; 2] = [6, 2];
+/// Index: `CastleType` enum number
+static POST_CASTLE_ROOK_X: [u8; 2] = [5, 3];
 
-    pub pre_castle_king_sq: [FastCoord; 2],
-    pub post_castle_king_sq: [[FastCoord; 2]; 2]
+/// Everything needed to generate and make one castle, for one player and one pair of king and rook start files
+pub struct CastleSquares {
+    /// Every square touched by the king or rook, each appearing once, so the castle can be applied in any order
+    pub sqs: Vec<BeforeAfterSquare>,
+    /// Squares which the king or rook travels through, excluding the king and the rook
+    pub blank_coords: Vec<FastCoord>,
+    /// Squares the king travels through which can't be attacked, excluding the king's start square
+    pub king_traversal_coords: Vec<FastCoord>,
+    /// (from, to) drags for the UI, excludes drags which are also normal king moves
+    pub draggable_coords: Vec<(FastCoord, FastCoord)>,
+    pub rook_sq: FastCoord,
+    pub post_castle_king_sq: FastCoord
 }
 
-impl CastleUtils {
+impl CastleSquares {
 
-    fn oo_squares_for_row(player: Player) -> [BeforeAfterSquare; 4] {
+    fn new(castle_type: CastleType, player: Player, king_x: u8, rook_x: u8) -> CastleSquares {
         let y = player.first_row();
-        [
-            BeforeAfterSquare(FastCoord::from_xy(4, y), Square::Occupied(Piece::King, player), Square::Blank),
-            BeforeAfterSquare(FastCoord::from_xy(5, y), Square::Blank, Square::Occupied(Piece::Rook, player)),
-            BeforeAfterSquare(FastCoord::from_xy(6, y), Square::Blank, Square::Occupied(Piece::King, player)),
-            BeforeAfterSquare(FastCoord::from_xy(7, y), Square::Occupied(Piece::Rook, player), Square::Blank)
-        ]
-    }
+        let king_to_x = POST_CASTLE_KING_X[castle_type as usize];
+        let rook_to_x = POST_CASTLE_ROOK_X[castle_type as usize];
 
-    fn ooo_squares_for_row(player: Player) -> [BeforeAfterSquare; 5] {
-        let y = player.first_row();
-        [
-            BeforeAfterSquare(FastCoord::from_xy(0, y), Square::Occupied(Piece::Rook, player), Square::Blank),
-            BeforeAfterSquare(FastCoord::from_xy(1, y), Square::Blank, Square::Blank),
-            BeforeAfterSquare(FastCoord::from_xy(2, y), Square::Blank, Square::Occupied(Piece::King, player)),
-            BeforeAfterSquare(FastCoord::from_xy(3, y), Square::Blank, Square::Occupied(Piece::Rook, player)),
-            BeforeAfterSquare(FastCoord::from_xy(4, y), Square::Occupied(Piece::King, player), Square::Blank)
-        ]
+        let mut sqs = Vec::new();
+        let mut blank_coords = Vec::new();
+        let min_x = *[king_x, rook_x, king_to_x, rook_to_x].iter().min().unwrap();
+        let max_x = *[king_x, rook_x, king_to_x, rook_to_x].iter().max().unwrap();
+        for x in min_x..=max_x {
+            let before = if x == king_x {
+                Square::Occupied(Piece::King, player)
+            } else if x == rook_x {
+                Square::Occupied(Piece::Rook, player)
+            } else {
+                Square::Blank
+            };
+            let after = if x == king_to_x {
+                Square::Occupied(Piece::King, player)
+            } else if x == rook_to_x {
+                Square::Occupied(Piece::Rook, player)
+            } else {
+                Square::Blank
+            };
+            if before != Square::Blank || after != Square::Blank {
+                sqs.push(BeforeAfterSquare(FastCoord::from_xy(x, y), before, after));
+            }
+            if before == Square::Blank {
+                blank_coords.push(FastCoord::from_xy(x, y));
+            }
+        }
+
+        let king_traversal_coords = if king_x < king_to_x {
+            (king_x + 1..=king_to_x).map(|x| FastCoord::from_xy(x, y)).collect()
+        } else {
+            (king_to_x..king_x).rev().map(|x| FastCoord::from_xy(x, y)).collect()
+        };
+
+        let king_sq = FastCoord::from_xy(king_x, y);
+        let rook_sq = FastCoord::from_xy(rook_x, y);
+        let post_castle_king_sq = FastCoord::from_xy(king_to_x, y);
+        let mut draggable_coords = vec![(king_sq, rook_sq), (rook_sq, king_sq)];
+        if (king_x as i32 - king_to_x as i32).abs() >= 2 {
+            draggable_coords.push((king_sq, post_castle_king_sq));
+        }
+
+        CastleSquares {
+            sqs,
+            blank_coords,
+            king_traversal_coords,
+            draggable_coords,
+            rook_sq,
+            post_castle_king_sq
+        }
     }
+}
+
+/// Castle squares for every possible king and rook start file, since Chess960 moves them
+pub struct CastleUtils {
+    /// Index: `CastleType` enum number, `Player` enum number, king start x, rook start x
+    squares: Vec<CastleSquares>,
+
+    pub pre_castle_king_sq: [FastCoord; 2]
+}
+
+impl CastleUtils {
 
     pub fn new() -> CastleUtils {
         console_log!("Generating castle constants");
 
-        let white_first_row = Player::first_row(Player::White);
-        let black_first_row = Player::first_row(Player::Black);
-
-        return CastleUtils {
-            oo_sqs: [
-                CastleUtils::oo_squares_for_row(Player::White),
-                CastleUtils::oo_squares_for_row(Player::Black)
-            ],
-            ooo_sqs: [
-                CastleUtils::ooo_squares_for_row(Player::White),
-                CastleUtils::ooo_squares_for_row(Player::Black)
-            ],
-            king_traversal_coords: [[
-                [FastCoord::from_xy(6, white_first_row), FastCoord::from_xy(5, white_first_row)],
-                [FastCoord::from_xy(6, black_first_row), FastCoord::from_xy(5, black_first_row)]
-            ], [
-                [FastCoord::from_xy(2, white_first_row), FastCoord::from_xy(3, white_first_row)],
-                [FastCoord::from_xy(2, black_first_row), FastCoord::from_xy(3, black_first_row)]
-            ]],
-            oo_blank_coords: [
-                [FastCoord::from_xy(6, white_first_row), FastCoord::from_xy(5, white_first_row)],
-                [FastCoord::from_xy(6, black_first_row), FastCoord::from_xy(5, black_first_row)]
-            ],
-            ooo_blank_coords: [
-                [FastCoord::from_xy(1, white_first_row), FastCoord::from_xy(2, white_first_row), FastCoord::from_xy(3, white_first_row)],
-                [FastCoord::from_xy(1, black_first_row), FastCoord::from_xy(2, black_first_row), FastCoord::from_xy(3, black_first_row)]
-            ],
-            draggable_coords: [[[
-                (FastCoord::from_xy(4, 7), FastCoord::from_xy(7, 7)),
-                (FastCoord::from_xy(7, 7), FastCoord::from_xy(4, 7)),
-                (FastCoord::from_xy(4, 7), FastCoord::from_xy(6, 7))
-            ], [
-                (FastCoord::from_xy(4, 0), FastCoord::from_xy(7, 0)),
-                (FastCoord::from_xy(7, 0), FastCoord::from_xy(4, 0)),
-                (FastCoord::from_xy(4, 0), FastCoord::from_xy(6, 0))
-            ]], [[
-                (FastCoord::from_xy(0, 7), FastCoord::from_xy(4, 7)),
-                (FastCoord::from_xy(4, 7), FastCoord::from_xy(0, 7)),
-                (FastCoord::from_xy(4, 7), FastCoord::from_xy(2, 7))
-            ], [
-                (FastCoord::from_xy(0, 0), FastCoord::from_xy(4, 0)),
-                (FastCoord::from_xy(4, 0), FastCoord::from_xy(0, 0)),
-                (FastCoord::from_xy(4, 0), FastCoord::from_xy(2, 0))
-            ]]],
-            pre_castle_king_sq: [FastCoord::from_xy(4, 7), FastCoord::from_xy(4, 0)],
-            post_castle_king_sq: [
-                [FastCoord::from_xy(6, 7), FastCoord::from_xy(6, 0)],
-                [FastCoord::from_xy(2, 7), FastCoord::from_xy(2, 0)]
+        let mut squares = Vec::with_capacity(2 * 2 * 8 * 8);
+        for castle_type in [CastleType::Oo, CastleType::Ooo].iter() {
+            for player in [Player::White, Player::Black].iter() {
+                for king_x in 0..8 {
+                    for rook_x in 0..8 {
+                        squares.push(CastleSquares::new(*castle_type, *player, king_x, rook_x));
+                    }
+                }
+            }
+        }
+
+        CastleUtils {
+            squares,
+            pre_castle_king_sq: [
+                FastCoord::from_xy(STANDARD_KING_X, Player::White.first_row()),
+                FastCoord::from_xy(STANDARD_KING_X, Player::Black.first_row())
             ]
-        };
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, castle_type: CastleType, player: Player, king_x: u8, rook_x: u8) -> &CastleSquares {
+        &self.squares[(((castle_type as usize) * 2 + player as usize) * 8 + king_x as usize) * 8 + rook_x as usize]
     }
 }
@@ -1,12 +1,38 @@
 use std::fmt::{Error as FmtError, Display, Formatter};
 
 #[repr(u8)]
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Piece {
     Pawn = 0, Rook, Knight, Bishop, Queen, King
 }
 
+static NUM_TO_PIECE: [Piece; 6] = [Piece::Pawn, Piece::Rook, Piece::Knight, Piece::Bishop, Piece::Queen, Piece::King];
+static PIECE_TO_LETTER: [char; 6] = ['P', 'R', 'N', 'B', 'Q', 'K'];
+
+/// Pieces a pawn can promote to, best first
+pub static PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop];
+
+/// Rook and bishop promotions are almost never better than a queen, so don't spend time on them in tactical searches
+pub static TACTICAL_PROMOTION_PIECES: [Piece; 2] = [Piece::Queen, Piece::Knight];
+
 impl Piece {
+
+    /// Inverse of the `Piece` enum number
+    pub fn from_num(num: u8) -> Option<Piece> {
+        NUM_TO_PIECE.get(num as usize).copied()
+    }
+
+    /// Upper case letter, eg. N for knight
+    pub fn to_letter(self) -> char {
+        PIECE_TO_LETTER[self as usize]
+    }
+
+    /// Case insensitive, inverse of `to_letter`
+    pub fn from_letter(letter: char) -> Option<Piece> {
+        let upper = letter.to_ascii_uppercase();
+        PIECE_TO_LETTER.iter().position(|l| *l == upper).and_then(|i| Piece::from_num(i as u8))
+    }
+
     fn custom_fmt(&self, f: &mut Formatter<'_>, is_lower: bool) -> Result<(), FmtError> {
         let s = match self {
             Piece::Pawn => "P",
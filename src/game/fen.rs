@@ -0,0 +1,345 @@
+use std::fmt::{Display, Formatter, self};
+use super::board::*;
+use super::coords::*;
+use super::entities::*;
+use super::move_list::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    /// (fields found)
+    WrongFieldCount(usize),
+    /// (rows found)
+    WrongRowCount(usize),
+    /// (rank of the row)
+    WrongRowLength(u8),
+    UnknownPiece(char),
+    UnknownTurn,
+    UnknownCastleRight(char),
+    /// (castle right letter)
+    CastleRightWithoutPieces(char),
+    BadEnPassant,
+    BadMoveCounter,
+    /// (player, kings found)
+    WrongKingCount(Player, usize)
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            FenError::WrongFieldCount(n) => write!(f, "Expected 4 to 6 space separated fields, found {}", n),
+            FenError::WrongRowCount(n) => write!(f, "Expected 8 rows of pieces, found {}", n),
+            FenError::WrongRowLength(rank) => write!(f, "Row for rank {} does not have 8 squares", rank),
+            FenError::UnknownPiece(c) => write!(f, "Unknown piece '{}'", c),
+            FenError::UnknownTurn => write!(f, "Side to move must be 'w' or 'b'"),
+            FenError::UnknownCastleRight(c) => write!(f, "Unknown castle right '{}'", c),
+            FenError::CastleRightWithoutPieces(c) => write!(f, "Castle right '{}' needs the king and rook on their starting squares", c),
+            FenError::BadEnPassant => write!(f, "En passant square must be '-', or on rank 3 or 6 behind a pawn which just moved"),
+            FenError::BadMoveCounter => write!(f, "Move counters must be non-negative numbers, and the fullmove number must be at least 1"),
+            FenError::WrongKingCount(player, n) => write!(f, "{:?} must have exactly 1 king, found {}", player, n)
+        }
+    }
+}
+
+/// Index: `CastleType` enum number, then `Player` enum number
+static CASTLE_RIGHT_LETTERS: [[char; 2]; 2] = [['K', 'k'], ['Q', 'q']];
+
+/// Outermost rook on the first row, on the castle side of the king
+fn find_outer_rook_x(board: &Board, castle_type: CastleType, player: Player, king_x: u8) -> Option<u8> {
+    let y = player.first_row();
+    let is_rook = |x: &u8| *board.get_by_xy(*x, y) == Square::Occupied(Piece::Rook, player);
+    if castle_type == CastleType::Oo {
+        (king_x + 1..8).rev().find(is_rook)
+    } else {
+        (0..king_x).find(is_rook)
+    }
+}
+
+/// Accepts KQkq, where the castling rook is the outermost one, and X-FEN/Shredder-FEN file letters for Chess960.
+/// Returns (castle type, player, king start x, rook start x).
+fn parse_castle_right(board: &Board, letter: char) -> Result<(CastleType, Player, u8, u8), FenError> {
+    let player = if letter.is_ascii_uppercase() { Player::White } else { Player::Black };
+    let y = player.first_row();
+    let lower = letter.to_ascii_lowercase();
+    if lower != 'k' && lower != 'q' && !('a'..='h').contains(&lower) {
+        return Err(FenError::UnknownCastleRight(letter));
+    }
+
+    let king_x = (0..8).find(|x| *board.get_by_xy(*x, y) == Square::Occupied(Piece::King, player))
+        .ok_or(FenError::CastleRightWithoutPieces(letter))?;
+
+    let (castle_type, rook_x) = match lower {
+        'k' => (CastleType::Oo, find_outer_rook_x(board, CastleType::Oo, player, king_x)),
+        'q' => (CastleType::Ooo, find_outer_rook_x(board, CastleType::Ooo, player, king_x)),
+        _ => {
+            let x = lower as u8 - b'a';
+            let castle_type = if x > king_x { CastleType::Oo } else { CastleType::Ooo };
+            let is_rook = *board.get_by_xy(x, y) == Square::Occupied(Piece::Rook, player);
+            (castle_type, if is_rook { Some(x) } else { None })
+        }
+    };
+
+    match rook_x {
+        Some(rook_x) => Ok((castle_type, player, king_x, rook_x)),
+        None => Err(FenError::CastleRightWithoutPieces(letter))
+    }
+}
+
+fn parse_placement(board: &mut Board, placement: &str) -> Result<(), FenError> {
+    let rows: Vec<&str> = placement.split('/').collect();
+    if rows.len() != 8 {
+        return Err(FenError::WrongRowCount(rows.len()));
+    }
+
+    for (y, row) in rows.iter().enumerate() {
+        let rank = 8 - y as u8;
+        let mut x: u8 = 0;
+        for c in row.chars() {
+            if let Some(skip) = c.to_digit(10) {
+                x += skip as u8;
+                if x > 8 {
+                    return Err(FenError::WrongRowLength(rank));
+                }
+            } else {
+                let piece = Piece::from_letter(c).ok_or(FenError::UnknownPiece(c))?;
+                let player = if c.is_ascii_uppercase() { Player::White } else { Player::Black };
+                if x >= 8 {
+                    return Err(FenError::WrongRowLength(rank));
+                }
+                board.set_by_index(y as u8 * 8 + x, Square::Occupied(piece, player));
+                x += 1;
+            }
+        }
+        if x != 8 {
+            return Err(FenError::WrongRowLength(rank));
+        }
+    }
+
+    for player in [Player::White, Player::Black].iter() {
+        let king_count = (0..64u8).filter(|i| *board.get_by_index(*i) == Square::Occupied(Piece::King, *player)).count();
+        if king_count != 1 {
+            return Err(FenError::WrongKingCount(*player, king_count));
+        }
+    }
+    Ok(())
+}
+
+fn parse_en_passant(board: &Board, s: &str) -> Result<Option<FastCoord>, FenError> {
+    if s == "-" {
+        return Ok(None);
+    }
+
+    let mut chars = s.chars();
+    let (file, rank_char) = match (chars.next(), chars.next(), chars.next()) {
+        (Some(file), Some(rank_char), None) => (file, rank_char),
+        _ => return Err(FenError::BadEnPassant)
+    };
+    let rank = rank_char.to_digit(10).ok_or(FenError::BadEnPassant)? as u8;
+    let coord = file_rank_to_xy_safe(file, rank).map_err(|_| FenError::BadEnPassant)?;
+
+    // The pawn which just moved belongs to the player without the turn, and is one row past the en passant square
+    let moved_player = board.get_player_with_turn().other_player();
+    let expected_rank = if moved_player == Player::White { 3 } else { 6 };
+    if rank != expected_rank {
+        return Err(FenError::BadEnPassant);
+    }
+    let pawn_y = if moved_player == Player::White { coord.1 - 1 } else { coord.1 + 1 };
+    if *board.get_by_xy(coord.0, coord.1) != Square::Blank ||
+        *board.get_by_xy(coord.0, pawn_y) != Square::Occupied(Piece::Pawn, moved_player) {
+        return Err(FenError::BadEnPassant);
+    }
+
+    // Like after the double push itself, the square is dropped when no pawn can capture, so the position hashes the same
+    if !board.has_en_passant_capturer(Coord(coord.0, pawn_y), board.get_player_with_turn()) {
+        return Ok(None);
+    }
+    Ok(Some(FastCoord::from_coord(&coord)))
+}
+
+fn parse_counter(s: Option<&&str>, default: u32) -> Result<u32, FenError> {
+    match s {
+        Some(s) => s.parse::<u32>().map_err(|_| FenError::BadMoveCounter),
+        None => Ok(default)
+    }
+}
+
+impl Board {
+
+    /// The move counters may be left out, in which case they default to 0 and 1
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+
+        let mut board = Board::empty();
+        parse_placement(&mut board, fields[0])?;
+
+        match fields[1] {
+            "w" => board.set_player_with_turn(Player::White),
+            "b" => board.set_player_with_turn(Player::Black),
+            _ => return Err(FenError::UnknownTurn)
+        };
+
+        for player in [Player::White, Player::Black].iter() {
+            board.set_moved_castle_piece(*player, CastleType::Oo, true);
+            board.set_moved_castle_piece(*player, CastleType::Ooo, true);
+        }
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let (castle_type, player, king_x, rook_x) = parse_castle_right(&board, c)?;
+                let mut rook_start_x = board.get_player_state(player).rook_start_x;
+                rook_start_x[castle_type as usize] = rook_x;
+                board.set_castle_files(player, king_x, rook_start_x);
+                board.set_moved_castle_piece(player, castle_type, false);
+            }
+        }
+
+        let en_passant = parse_en_passant(&board, fields[3])?;
+        board.set_en_passant(en_passant);
+
+        let halfmove_clock = parse_counter(fields.get(4), 0)?;
+        let fullmove_number = parse_counter(fields.get(5), 1)?;
+        if fullmove_number == 0 {
+            return Err(FenError::BadMoveCounter);
+        }
+        board.set_move_counters(halfmove_clock, fullmove_number);
+
+        board.refresh_king_locations();
+        board.refresh_hash();
+        Ok(board)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for y in 0..8 {
+            if y != 0 {
+                placement.push('/');
+            }
+            let mut blanks = 0;
+            for x in 0..8 {
+                match self.get_by_xy(x, y) {
+                    Square::Occupied(piece, player) => {
+                        if blanks != 0 {
+                            placement.push_str(&blanks.to_string());
+                            blanks = 0;
+                        }
+                        let letter = piece.to_letter();
+                        placement.push(if *player == Player::White { letter } else { letter.to_ascii_lowercase() });
+                    },
+                    Square::Blank => blanks += 1
+                }
+            }
+            if blanks != 0 {
+                placement.push_str(&blanks.to_string());
+            }
+        }
+
+        let turn = if self.get_player_with_turn() == Player::White { "w" } else { "b" };
+
+        let mut castle_rights = String::new();
+        for player in [Player::White, Player::Black].iter() {
+            let player_state = self.get_player_state(*player);
+            for castle_type in [CastleType::Oo, CastleType::Ooo].iter() {
+                if !player_state.moved_castle_piece[*castle_type as usize] {
+                    let rook_x = player_state.rook_start_x[*castle_type as usize];
+                    if find_outer_rook_x(self, *castle_type, *player, player_state.king_start_x) == Some(rook_x) {
+                        castle_rights.push(CASTLE_RIGHT_LETTERS[*castle_type as usize][*player as usize]);
+                    } else {
+                        let file = (b'a' + rook_x) as char;
+                        castle_rights.push(if *player == Player::White { file.to_ascii_uppercase() } else { file });
+                    }
+                }
+            }
+        }
+        if castle_rights.is_empty() {
+            castle_rights.push('-');
+        }
+
+        let en_passant = match self.get_en_passant() {
+            Some(coord) => coord.to_string(),
+            None => String::from("-")
+        };
+
+        format!("{} {} {} {} {} {}", placement, turn, castle_rights, en_passant, self.get_halfmove_clock(), self.get_fullmove_number())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use super::super::bitboard::*;
+
+    static STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    static KIWIPETE_FEN: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn starting_fen_matches_new_board() {
+        let board = Board::from_fen(STARTING_FEN).unwrap();
+        let new_board = Board::new();
+        assert_eq!(board.to_fen(), STARTING_FEN);
+        assert_eq!(new_board.to_fen(), STARTING_FEN);
+        assert_eq!(board.get_hash(), new_board.get_hash());
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        let fens = [
+            KIWIPETE_FEN,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 2 4"
+        ];
+        for fen in fens.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            board.assert_hash();
+            assert_eq!(board.to_fen(), *fen);
+        }
+    }
+
+    #[test]
+    fn fen_state() {
+        let board = Board::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 5 3").unwrap();
+        assert_eq!(board.get_player_with_turn(), Player::White);
+        assert!(board.get_en_passant() == Some(FastCoord::from_xy(5, 2)));
+        assert_eq!(board.get_player_state(Player::White).moved_castle_piece, [false, true]);
+        assert_eq!(board.get_player_state(Player::Black).moved_castle_piece, [true, false]);
+        assert_eq!(board.get_halfmove_clock(), 5);
+        assert_eq!(board.get_fullmove_number(), 3);
+        assert!(board.get_player_state(Player::Black).king_location == Bitboard::from_index(4));
+    }
+
+    #[test]
+    fn fen_drops_unusable_en_passant() {
+        let board = Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        assert!(board.get_en_passant().is_none());
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+
+        let mut played = Board::new();
+        played.handle_move(&played.parse_uci_move("e2e4").unwrap());
+        assert_eq!(board.get_hash(), played.get_hash());
+    }
+
+    #[test]
+    fn fen_without_counters() {
+        let board = Board::from_fen("8/8/8/8/8/8/8/K6k b - -").unwrap();
+        assert_eq!(board.to_fen(), "8/8/8/8/8/8/8/K6k b - - 0 1");
+    }
+
+    #[test]
+    fn fen_errors() {
+        assert_eq!(Board::from_fen("").err(), Some(FenError::WrongFieldCount(0)));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8 w - - 0 1").err(), Some(FenError::WrongRowCount(7)));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K5k w - - 0 1").err(), Some(FenError::WrongRowLength(1)));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6kp w - - 0 1").err(), Some(FenError::WrongRowLength(1)));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6x w - - 0 1").err(), Some(FenError::UnknownPiece('x')));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K7 w - - 0 1").err(), Some(FenError::WrongKingCount(Player::Black, 0)));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6k x - - 0 1").err(), Some(FenError::UnknownTurn));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6k w X - 0 1").err(), Some(FenError::UnknownCastleRight('X')));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6k w K - 0 1").err(), Some(FenError::CastleRightWithoutPieces('K')));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6k w - e3 0 1").err(), Some(FenError::BadEnPassant));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6k w - - x 1").err(), Some(FenError::BadMoveCounter));
+        assert_eq!(Board::from_fen("8/8/8/8/8/8/8/K6k w - - 0 0").err(), Some(FenError::BadMoveCounter));
+    }
+}
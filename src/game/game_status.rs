@@ -0,0 +1,71 @@
+use super::board::*;
+use super::entities::*;
+use super::move_list::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DrawReason {
+    Repetition,
+    FiftyMove,
+    InsufficientMaterial
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// (winner)
+    Checkmate(Player),
+    Stalemate,
+    Draw(DrawReason)
+}
+
+impl Board {
+
+    /// Checkmate and stalemate take precedence over the other draw reasons. `result` is used as scratch space
+    /// from its current write index, which is restored afterwards.
+    pub fn get_game_status(&mut self, result: &mut MoveList) -> GameStatus {
+        let start = result.write_index;
+        self.get_moves(result);
+        let has_moves = result.write_index > start;
+        result.write_index = start;
+
+        let curr_player = self.get_player_with_turn();
+        if !has_moves {
+            if self.is_checking(curr_player.other_player()) {
+                GameStatus::Checkmate(curr_player.other_player())
+            } else {
+                GameStatus::Stalemate
+            }
+        } else if self.is_insufficient_material() {
+            GameStatus::Draw(DrawReason::InsufficientMaterial)
+        } else if self.is_fifty_move_draw() {
+            GameStatus::Draw(DrawReason::FiftyMove)
+        } else if self.is_threefold_repetition() {
+            GameStatus::Draw(DrawReason::Repetition)
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn status(fen: &str) -> GameStatus {
+        let mut board = Board::from_fen(fen).unwrap();
+        board.get_game_status(&mut MoveList::new(50))
+    }
+
+    #[test]
+    fn game_statuses() {
+        assert_eq!(status("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), GameStatus::Ongoing);
+        // Fool's mate
+        assert_eq!(status("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"), GameStatus::Checkmate(Player::Black));
+        assert_eq!(status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), GameStatus::Stalemate);
+        assert_eq!(status("8/8/4k3/8/8/4K3/8/5N2 w - - 0 1"), GameStatus::Draw(DrawReason::InsufficientMaterial));
+        assert_eq!(status("8/8/4k3/8/8/4K3/8/R7 w - - 100 80"), GameStatus::Draw(DrawReason::FiftyMove));
+        // Mate on the last move before the fifty-move limit still counts
+        assert_eq!(status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80"), GameStatus::Checkmate(Player::White));
+    }
+}
@@ -1,19 +1,17 @@
-use super::super::extern_funcs::random;
-use super::super::*;
+/// Seed of the keys. Changing it changes every hash
+const KEYS_SEED: u64 = 0x4C4A_454E_4B53_4348;
 
-fn get_random_u64() -> u64 {
-    let mut a = ((u16::MAX as f64) * random()) as u64;
+/// SplitMix64, a small PRNG which is good enough for hash keys. Fixed seeds make hashes the same on every run and platform
+struct SplitMix64(u64);
 
-    a <<= 16;
-    a += ((u16::MAX as f64) * random()) as u64;
-
-    a <<= 16;
-    a += ((u16::MAX as f64) * random()) as u64;
-
-    a <<= 16;
-    a += ((u16::MAX as f64) * random()) as u64;
-    
-    a
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
 }
 
 pub const PIECE_LEN: usize = 6;
@@ -24,20 +22,46 @@ pub struct RandomNumberKeys {
     pub squares: [u64; SQUARES_LEN],
     /// First index: `CastleType` enum number. Second index: `Player` enum number.
     pub moved_castle_piece: [[u64; 2]; 2],
+    /// Index: x coordinate of the en passant square
+    pub en_passant_file: [u64; 8],
     pub is_white_to_play: u64
 }
 
 impl RandomNumberKeys {
     pub fn new() -> RandomNumberKeys {
-        console_log!("Generating random number keys for hashing");
+        let mut rng = SplitMix64(KEYS_SEED);
         let mut squares = [0u64; SQUARES_LEN];
-        for i in 0..squares.len() {
-            squares[i] = get_random_u64();
+        for key in squares.iter_mut() {
+            *key = rng.next_u64();
+        }
+        let moved_castle_piece = [[rng.next_u64(), rng.next_u64()], [rng.next_u64(), rng.next_u64()]];
+        let mut en_passant_file = [0u64; 8];
+        for key in en_passant_file.iter_mut() {
+            *key = rng.next_u64();
         }
         RandomNumberKeys {
             squares,
-            moved_castle_piece: [[get_random_u64(), get_random_u64()], [get_random_u64(), get_random_u64()]],
-            is_white_to_play: get_random_u64()
+            moved_castle_piece,
+            en_passant_file,
+            is_white_to_play: rng.next_u64()
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use super::super::board::*;
+
+    #[test]
+    fn keys_are_fixed() {
+        // Reference values of SplitMix64 seeded with 0
+        let mut rng = SplitMix64(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+
+        // Hashes may be persisted, so they must not change between runs
+        assert_eq!(format!("{:016X}", Board::new().get_hash()), "50114CED57048114");
+    }
+}
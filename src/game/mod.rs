@@ -8,3 +8,12 @@ pub mod move_test;
 pub mod searchable_moves;
 pub mod bitboard;
 pub mod bitboard_presets;
+pub mod fen;
+pub mod game_status;
+pub mod perft;
+pub mod position_editor;
+pub mod move_history;
+pub mod san;
+pub mod uci_move;
+pub mod pgn;
+pub mod polyglot;
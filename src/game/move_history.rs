@@ -0,0 +1,145 @@
+use super::board::*;
+use super::move_list::*;
+
+/// Moves played on a board, for takebacks. Reverting restores the hash history and halfmove clock too,
+/// so repetition and fifty move draws are rewound with the position.
+pub struct MoveHistory {
+    /// Position before the first move
+    start: Board,
+    /// (move, how to revert it, SAN)
+    played: Vec<(MoveDescription, RevertableMove, String)>,
+    /// (move, SAN), most recently undone move last
+    undone: Vec<(MoveDescription, String)>
+}
+
+impl MoveHistory {
+
+    pub fn new(start: &Board) -> Self {
+        Self {
+            start: start.clone(),
+            played: Vec::new(),
+            undone: Vec::new()
+        }
+    }
+
+    /// Makes the move, which must be legal on `board`. Clears the moves which could be redone
+    pub fn play(&mut self, board: &mut Board, m: &MoveWithEval) {
+        self.undone.clear();
+        let san = board.get_san(m);
+        self.push(board, m.description().clone(), san);
+    }
+
+    fn push(&mut self, board: &mut Board, description: MoveDescription, san: String) {
+        let revertable = board.handle_move(&MoveWithEval(description.clone(), 0));
+        self.played.push((description, revertable, san));
+    }
+
+    /// False if there is no move to undo
+    pub fn undo(&mut self, board: &mut Board) -> bool {
+        match self.played.pop() {
+            Some((description, revertable, san)) => {
+                board.revert_move(&revertable);
+                self.undone.push((description, san));
+                true
+            },
+            None => false
+        }
+    }
+
+    /// False if there is no undone move to redo
+    pub fn redo(&mut self, board: &mut Board) -> bool {
+        match self.undone.pop() {
+            Some((description, san)) => {
+                self.push(board, description, san);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Moves played, excluding undone ones
+    pub fn len(&self) -> usize {
+        self.played.len()
+    }
+
+    pub fn get_start(&self) -> &Board {
+        &self.start
+    }
+
+    /// SAN of the played moves, oldest first
+    pub fn get_sans(&self) -> Vec<&str> {
+        self.played.iter().map(|(_, _, san)| san.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn find_move(board: &Board, from_to: &str) -> MoveWithEval {
+        let mut moves = MoveList::new(100);
+        board.get_moves(&mut moves);
+        moves.v()[0..moves.write_index].iter()
+            .find(|m| matches!(m.description(), MoveDescription::NormalMove(from, to) if format!("{}{}", from, to) == from_to))
+            .unwrap()
+            .clone()
+    }
+
+    fn play_all(history: &mut MoveHistory, board: &mut Board, moves: &[&str]) {
+        for from_to in moves.iter() {
+            let m = find_move(board, from_to);
+            history.play(board, &m);
+        }
+    }
+
+    #[test]
+    fn undo_redo_restores_positions() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/3p4/8/4P3/R3K2R w KQkq - 3 10").unwrap();
+        let mut history = MoveHistory::new(&board);
+        let start_fen = board.to_fen();
+
+        play_all(&mut history, &mut board, &["e2e4", "d4e3", "a1a8"]);
+        let end_fen = board.to_fen();
+        let end_hash = board.get_hash();
+        assert_eq!(history.len(), 3);
+
+        while history.undo(&mut board) {}
+        assert_eq!(history.len(), 0);
+        assert_eq!(board.to_fen(), start_fen);
+        assert_eq!(board.get_hash_history_len(), 0);
+        board.assert_hash();
+        board.assert_piece_locs();
+
+        while history.redo(&mut board) {}
+        assert_eq!(history.len(), 3);
+        assert_eq!(board.to_fen(), end_fen);
+        assert_eq!(board.get_hash(), end_hash);
+        assert_eq!(history.get_sans(), vec!["e4", "dxe3", "Rxa8+"]);
+    }
+
+    #[test]
+    fn undo_rewinds_repetition() {
+        let mut board = Board::new();
+        let mut history = MoveHistory::new(&board);
+        play_all(&mut history, &mut board, &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert!(board.is_threefold_repetition());
+
+        assert!(history.undo(&mut board));
+        assert!(!board.is_threefold_repetition());
+        assert_eq!(board.get_halfmove_clock(), 7);
+    }
+
+    #[test]
+    fn playing_clears_redo() {
+        let mut board = Board::new();
+        let mut history = MoveHistory::new(&board);
+        play_all(&mut history, &mut board, &["e2e4", "e7e5"]);
+        assert!(history.undo(&mut board));
+
+        play_all(&mut history, &mut board, &["c7c5"]);
+        assert!(!history.redo(&mut board));
+        assert_eq!(history.len(), 2);
+        assert_eq!(board.to_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+    }
+}
@@ -9,15 +9,17 @@ pub struct BeforeSquare(pub FastCoord, pub Square);
 pub struct BeforeAfterSquare(pub FastCoord, pub Square, pub Square);
 
 #[repr(u8)]
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum CastleType {
     Oo = 0, Ooo
 }
 
 /// Keep minimal in size, to make move generation fast, and move execution slower
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub enum MoveDescription {
     NormalMove(FastCoord, FastCoord),
+    /// (from, to, piece to promote to)
+    Promotion(FastCoord, FastCoord, Piece),
     Castle(CastleType),
     SkipMove
 }
@@ -17,50 +17,32 @@ impl AttackFromBoards {
     }
 }
 
-pub struct CheckCaptureParams<'a> {
-    pub curr_player_piece_locs: &'a Bitboard,
-    pub opponent_piece_locs: &'a Bitboard,
-    pub king_potential_rook_atks: Bitboard,
-    pub king_potential_bishop_atks: Bitboard,
-    pub king_potential_knight_atks: Bitboard,
-    pub king_potential_pawn_atks: Bitboard
-}
-
-fn set_blockable_ray(
-    b: &mut Bitboard,
-    origin: FastCoord,
-    direction: RayDirection,
-    ensure_blocker_index: usize,
-    get_first_blocker_index: impl FnOnce(&Bitboard) -> u8,
-    blockers: &Bitboard
-) {
-    let direction_num = direction as usize;
-    let ray = BITBOARD_PRESETS.rays[direction_num][origin.0 as usize];
-    // There will always be at least 1 blocker, even for an empty board
-    let blocked_at = Bitboard((ray.0 & blockers.0) | BITBOARD_PRESETS.ensure_blocker[ensure_blocker_index].0);
-    let first_blocked_at_index = get_first_blocker_index(&blocked_at);
-    let past_blocked_ray = BITBOARD_PRESETS.rays[direction_num][first_blocked_at_index as usize].0;
-    let moves = Bitboard(past_blocked_ray ^ ray.0);
-    b.0 |= moves.0;
-}
-
 pub fn consume_to_move_list(b: &mut Bitboard, origin: FastCoord, result: &mut MoveList) {
     b.consume_loop_indices(|dest| {
         result.write(MoveWithEval(MoveDescription::NormalMove(origin, FastCoord(dest)), 0));
     });
 }
 
+/// Same as `consume_to_move_list`, except pawn moves onto the last row become one promotion per piece in `promotion_pieces`
+pub fn consume_pawn_to_move_list(b: &mut Bitboard, origin: FastCoord, player: Player, promotion_pieces: &[Piece], result: &mut MoveList) {
+    let promotion_row = BITBOARD_PRESETS.promotion_rows[player as usize];
+    let mut promotions = Bitboard(b.0 & promotion_row.0);
+    b.0 &= !promotion_row.0;
+
+    consume_to_move_list(b, origin, result);
+    promotions.consume_loop_indices(|dest| {
+        for piece in promotion_pieces.iter() {
+            result.write(MoveWithEval(MoveDescription::Promotion(origin, FastCoord(dest), *piece), 0));
+        }
+    });
+}
+
 pub fn update_attack_from_boards(origin: FastCoord, b: &mut Bitboard, result: &mut AttackFromBoards) {
     b.consume_loop_indices(|dest| {
         result.data[dest as usize].set_index(origin.0);
     });
 }
 
-#[inline]
-fn hits_king(b: &Bitboard, king_location: &Bitboard) -> bool {
-    (b.0 & king_location.0) != 0
-}
-
 #[inline]
 pub fn unset_own_pieces(b: &mut Bitboard, curr_player_piece_locs: &Bitboard) {
     b.0 &= !curr_player_piece_locs.0;
@@ -72,37 +54,17 @@ pub fn unset_own_pieces(b: &mut Bitboard, curr_player_piece_locs: &Bitboard) {
 #[inline]
 pub fn _write_rook_moves(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) -> Bitboard {
     let blockers = Bitboard(curr_player_piece_locs.0 | opponent_piece_locs.0);
-    
-    let mut b = Bitboard(0);
-    set_blockable_ray(&mut b, origin, RayDirection::Left, 0, Bitboard::_lsb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::Top, 0, Bitboard::_lsb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::Right, 1, Bitboard::_msb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::Bottom, 1, Bitboard::_msb_to_index, &blockers);
+
+    let mut b = BITBOARD_PRESETS.rook_attacks(origin.0, &blockers);
     unset_own_pieces(&mut b, curr_player_piece_locs);
     b
 }
 
-pub fn write_rook_moves(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) {
-    let mut b = _write_rook_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    consume_to_move_list(&mut b, origin, ml);
-}
-
-pub fn write_rook_ccs(ml: &mut MoveList, origin: FastCoord, params: &CheckCaptureParams) {
-    let mut b = _write_rook_moves(origin, params.curr_player_piece_locs, params.opponent_piece_locs);
-    b.0 &= params.opponent_piece_locs.0 | params.king_potential_rook_atks.0;
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn update_rook_af(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard, result: &mut AttackFromBoards) {
     let mut b = _write_rook_moves(origin, curr_player_piece_locs, opponent_piece_locs);
     update_attack_from_boards(origin, &mut b, result);
 }
 
-pub fn rook_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard, opponent_king_location: &Bitboard) -> bool {
-    let b = _write_rook_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    hits_king(&b, opponent_king_location)
-}
-
 //////////////////////////////////////////////////
 // Bishop 
 
@@ -110,36 +72,16 @@ pub fn rook_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, oppo
 pub fn _write_bishop_moves(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) -> Bitboard {
     let blockers = Bitboard(curr_player_piece_locs.0 | opponent_piece_locs.0);
 
-    let mut b = Bitboard(0);
-    set_blockable_ray(&mut b, origin, RayDirection::LeftTop, 0, Bitboard::_lsb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::RightTop, 0, Bitboard::_lsb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::RightBottom, 1, Bitboard::_msb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::LeftBottom, 1, Bitboard::_msb_to_index, &blockers);
+    let mut b = BITBOARD_PRESETS.bishop_attacks(origin.0, &blockers);
     unset_own_pieces(&mut b, curr_player_piece_locs);
     b
 }
 
-pub fn write_bishop_moves(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) {
-    let mut b = _write_bishop_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    consume_to_move_list(&mut b, origin, ml);
-}
-
-pub fn write_bishop_ccs(ml: &mut MoveList, origin: FastCoord, params: &CheckCaptureParams) {
-    let mut b = _write_bishop_moves(origin, &params.curr_player_piece_locs, &params.opponent_piece_locs);
-    b.0 &= params.opponent_piece_locs.0 | params.king_potential_bishop_atks.0;
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn update_bishop_af(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard, result: &mut AttackFromBoards) {
     let mut b = _write_bishop_moves(origin, curr_player_piece_locs, opponent_piece_locs);
     update_attack_from_boards(origin, &mut b, result);
 }
 
-pub fn bishop_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard, opponent_king_location: &Bitboard) -> bool {
-    let b = _write_bishop_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    hits_king(&b, opponent_king_location)
-}
-
 //////////////////////////////////////////////////
 // Queen 
 
@@ -147,47 +89,22 @@ pub fn bishop_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, op
 pub fn _write_queen_moves(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) -> Bitboard {
     let blockers = Bitboard(curr_player_piece_locs.0 | opponent_piece_locs.0);
 
-    let mut b = Bitboard(0);
-    set_blockable_ray(&mut b, origin, RayDirection::LeftTop, 0, Bitboard::_lsb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::RightTop, 0, Bitboard::_lsb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::RightBottom, 1, Bitboard::_msb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::LeftBottom, 1, Bitboard::_msb_to_index, &blockers);
-    
-    set_blockable_ray(&mut b, origin, RayDirection::Left, 0, Bitboard::_lsb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::Top, 0, Bitboard::_lsb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::Right, 1, Bitboard::_msb_to_index, &blockers);
-    set_blockable_ray(&mut b, origin, RayDirection::Bottom, 1, Bitboard::_msb_to_index, &blockers);
+    let mut b = Bitboard(BITBOARD_PRESETS.rook_attacks(origin.0, &blockers).0 | BITBOARD_PRESETS.bishop_attacks(origin.0, &blockers).0);
     unset_own_pieces(&mut b, curr_player_piece_locs);
     b
 }
 
-pub fn write_queen_moves(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) {
-    let mut b = _write_queen_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn write_queen_captures(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) {
     let mut b = _write_queen_moves(origin, curr_player_piece_locs, opponent_piece_locs);
     b.0 &= opponent_piece_locs.0;
     consume_to_move_list(&mut b, origin, ml);
 }
 
-pub fn write_queen_ccs(ml: &mut MoveList, origin: FastCoord, params: &CheckCaptureParams) {
-    let mut b = _write_queen_moves(origin, &params.curr_player_piece_locs, &params.opponent_piece_locs);
-    b.0 &= params.opponent_piece_locs.0 | params.king_potential_bishop_atks.0 | params.king_potential_rook_atks.0;
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn update_queen_af(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard, result: &mut AttackFromBoards) {
     let mut b = _write_queen_moves(origin, curr_player_piece_locs, opponent_piece_locs);
     update_attack_from_boards(origin, &mut b, result);
 }
 
-pub fn queen_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard, opponent_king_location: &Bitboard) -> bool {
-    let b = _write_queen_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    hits_king(&b, opponent_king_location)
-}
-
 //////////////////////////////////////////////////
 // Knight 
 
@@ -198,33 +115,17 @@ pub fn _write_knight_moves(origin: FastCoord, curr_player_piece_locs: &Bitboard)
     jumps
 }
 
-pub fn write_knight_moves(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard) {
-    let mut b = _write_knight_moves(origin, curr_player_piece_locs);
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn write_knight_captures(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) {
     let mut b = _write_knight_moves(origin, curr_player_piece_locs);
     b.0 &= opponent_piece_locs.0;
     consume_to_move_list(&mut b, origin, ml);
 }
 
-pub fn write_knight_ccs(ml: &mut MoveList, origin: FastCoord, params: &CheckCaptureParams) {
-    let mut b = _write_knight_moves(origin, &params.curr_player_piece_locs);
-    b.0 &= params.opponent_piece_locs.0 | params.king_potential_knight_atks.0;
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn update_knight_af(origin: FastCoord, curr_player_piece_locs: &Bitboard, result: &mut AttackFromBoards) {
     let mut b = _write_knight_moves(origin, curr_player_piece_locs);
     update_attack_from_boards(origin, &mut b, result);
 }
 
-pub fn knight_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_king_location: &Bitboard) -> bool {
-    let b = _write_knight_moves(origin, curr_player_piece_locs);
-    hits_king(&b, opponent_king_location)
-}
-
 //////////////////////////////////////////////////
 // King 
 
@@ -235,28 +136,11 @@ pub fn _write_king_moves(origin: FastCoord, curr_player_piece_locs: &Bitboard) -
     m
 }
 
-pub fn write_king_moves(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard) {
-    let mut b = _write_king_moves(origin, curr_player_piece_locs);
-    consume_to_move_list(&mut b, origin, ml);
-}
-
-pub fn write_king_captures(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) {
-    let mut b = _write_king_moves(origin, curr_player_piece_locs);
-    b.0 &= opponent_piece_locs.0;
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn update_king_af(origin: FastCoord, curr_player_piece_locs: &Bitboard, result: &mut AttackFromBoards) {
     let mut b = _write_king_moves(origin, curr_player_piece_locs);
     update_attack_from_boards(origin, &mut b, result);
 }
 
-// This is a case, this is what prevents kings from getting close
-pub fn king_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_king_location: &Bitboard) -> bool {
-    let b = _write_king_moves(origin, curr_player_piece_locs);
-    hits_king(&b, opponent_king_location)
-}
-
 //////////////////////////////////////////////////
 // Pawn 
 
@@ -310,27 +194,11 @@ pub fn _write_white_pawn_captures(
     _write_pawn_captures(origin, Player::White, opponent_piece_locs)
 }
 
-pub fn write_white_pawn_moves(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) {
-    let mut b = _write_white_pawn_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    consume_to_move_list(&mut b, origin, ml);
-}
-
-pub fn write_white_pawn_ccs(ml: &mut MoveList, origin: FastCoord, params: &CheckCaptureParams) {
-    let mut b = _write_white_pawn_captures(origin, &params.opponent_piece_locs);
-    b.0 |= _write_white_pawn_moves(origin, &params.curr_player_piece_locs, &params.opponent_piece_locs).0 & params.king_potential_pawn_atks.0;
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn update_white_pawn_af(origin: FastCoord, opponent_piece_locs: &Bitboard, result: &mut AttackFromBoards) {
     let mut b = _write_white_pawn_captures(origin, opponent_piece_locs);
     update_attack_from_boards(origin, &mut b, result);
 }
 
-pub fn white_pawn_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard, opponent_king_location: &Bitboard) -> bool {
-    let b = _write_white_pawn_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    hits_king(&b, opponent_king_location)
-}
-
 #[inline]
 pub fn _write_black_pawn_moves(
     origin: FastCoord,
@@ -348,27 +216,11 @@ pub fn _write_black_pawn_captures(
     _write_pawn_captures(origin, Player::Black, opponent_piece_locs)
 }
 
-pub fn write_black_pawn_moves(ml: &mut MoveList, origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard) {
-    let mut b = _write_black_pawn_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    consume_to_move_list(&mut b, origin, ml);
-}
-
-pub fn write_black_pawn_ccs(ml: &mut MoveList, origin: FastCoord, params: &CheckCaptureParams) {
-    let mut b = _write_black_pawn_captures(origin, &params.opponent_piece_locs);
-    b.0 |= _write_black_pawn_moves(origin, &params.curr_player_piece_locs, &params.opponent_piece_locs).0 & params.king_potential_pawn_atks.0;
-    consume_to_move_list(&mut b, origin, ml);
-}
-
 pub fn update_black_pawn_af(origin: FastCoord, opponent_piece_locs: &Bitboard, result: &mut AttackFromBoards) {
     let mut b = _write_black_pawn_captures(origin, opponent_piece_locs);
     update_attack_from_boards(origin, &mut b, result);
 }
 
-pub fn black_pawn_hits_king(origin: FastCoord, curr_player_piece_locs: &Bitboard, opponent_piece_locs: &Bitboard, opponent_king_location: &Bitboard) -> bool {
-    let b = _write_black_pawn_moves(origin, curr_player_piece_locs, opponent_piece_locs);
-    hits_king(&b, opponent_king_location)
-}
-
 //////////////////////////////////////////////////
 // Tests 
 
@@ -388,12 +240,7 @@ mod test {
         blockers.set(5, 5);
         blockers.set(1, 1);
 
-        let mut b = Bitboard(0);
-        set_blockable_ray(&mut b, origin, RayDirection::LeftTop, 0, Bitboard::_lsb_to_index, &blockers);
-        set_blockable_ray(&mut b, origin, RayDirection::RightTop, 0, Bitboard::_lsb_to_index, &blockers);
-        set_blockable_ray(&mut b, origin, RayDirection::RightBottom, 1, Bitboard::_msb_to_index, &blockers);
-        set_blockable_ray(&mut b, origin, RayDirection::LeftBottom, 1, Bitboard::_msb_to_index, &blockers);
-    
+        let b = BITBOARD_PRESETS.bishop_attacks(origin.0, &blockers);
         println!("{}", b);
     }
 
@@ -405,11 +252,7 @@ mod test {
         let mut blockers = Bitboard(0);
         blockers.set(1, 7);
 
-        let mut b = Bitboard(0);
-        set_blockable_ray(&mut b, origin, RayDirection::Left, 0, Bitboard::_lsb_to_index, &blockers);
-        set_blockable_ray(&mut b, origin, RayDirection::Top, 0, Bitboard::_lsb_to_index, &blockers);
-        set_blockable_ray(&mut b, origin, RayDirection::Right, 1, Bitboard::_msb_to_index, &blockers);
-        set_blockable_ray(&mut b, origin, RayDirection::Bottom, 1, Bitboard::_msb_to_index, &blockers);
+        let b = BITBOARD_PRESETS.rook_attacks(origin.0, &blockers);
         println!("{}", b);
     }
 }
@@ -0,0 +1,122 @@
+use super::board::*;
+use super::move_list::*;
+
+impl Board {
+
+    /// Number of leaf nodes of the legal move tree at `depth`
+    pub fn perft(&mut self, depth: u8) -> u64 {
+        let mut moves = MoveList::new(500);
+        self.perft_recurse(depth, &mut moves)
+    }
+
+    /// Perft split by root move, (move, leaf nodes under it) in move generation order
+    pub fn divide(&mut self, depth: u8) -> Vec<(String, u64)> {
+        let mut moves = MoveList::new(500);
+        self.get_moves(&mut moves);
+        let root_moves: Vec<MoveWithEval> = moves.v()[0..moves.write_index].to_vec();
+
+        root_moves.iter().map(|m| {
+            let move_string = self.get_uci_move(m);
+            let revertable = self.handle_move(m);
+            moves.write_index = 0;
+            let nodes = if depth <= 1 { 1 } else { self.perft_recurse(depth - 1, &mut moves) };
+            self.revert_move(&revertable);
+            (move_string, nodes)
+        }).collect()
+    }
+
+    /// Moves of each depth are stacked onto `moves`, starting from its write index
+    fn perft_recurse(&mut self, depth: u8, moves: &mut MoveList) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let start = moves.write_index;
+        self.get_moves(moves);
+        let end_exclusive = moves.write_index;
+
+        // Bulk counting, the moves are legal already
+        let nodes = if depth == 1 {
+            (end_exclusive - start) as u64
+        } else {
+            let mut nodes = 0;
+            for i in start..end_exclusive {
+                let revertable = self.handle_move(&moves.v()[i].clone());
+                nodes += self.perft_recurse(depth - 1, moves);
+                self.revert_move(&revertable);
+            }
+            nodes
+        };
+
+        moves.write_index = start;
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// (FEN, node counts starting from depth 1)
+    static PERFT_POSITIONS: [(&str, &[u64]); 8] = [
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281, 4865609]),
+        ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862, 4085603]),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238, 674624]),
+        ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467, 422333]),
+        ("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1", &[6, 264, 9467, 422333]),
+        ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379, 2103487]),
+        ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890, 3894594]),
+        // Chess960
+        ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189, 326672])
+    ];
+
+    fn check_perft(max_depth: usize) {
+        for (fen, counts) in PERFT_POSITIONS.iter() {
+            let mut board = Board::from_fen(fen).unwrap();
+            let hash = board.get_hash();
+            let fen_before = board.to_fen();
+            for (i, count) in counts.iter().enumerate().take(max_depth) {
+                assert_eq!(board.perft(i as u8 + 1), *count, "{} at depth {}", fen, i + 1);
+            }
+            assert_eq!(board.get_hash(), hash);
+            assert_eq!(board.to_fen(), fen_before);
+        }
+    }
+
+    #[test]
+    fn perft_shallow() {
+        check_perft(3);
+    }
+
+    /// Slow in debug builds, run with `cargo test --release -- --ignored`
+    #[ignore]
+    #[test]
+    fn perft_deep() {
+        check_perft(5);
+    }
+
+    /// Move generation speed, run with `cargo test --release perft_benchmark -- --ignored --nocapture`
+    #[ignore]
+    #[test]
+    fn perft_benchmark() {
+        for (fen, counts) in PERFT_POSITIONS[0..2].iter() {
+            let mut board = Board::from_fen(fen).unwrap();
+            let depth = counts.len() as u8;
+            let start = std::time::Instant::now();
+            let nodes = board.perft(depth);
+            let elapsed = start.elapsed().as_secs_f64();
+            println!("{} depth {}: {} nodes in {:.3}s, {:.0} nodes/s", fen, depth, nodes, elapsed, nodes as f64 / elapsed);
+        }
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let mut board = Board::from_fen(PERFT_POSITIONS[1].0).unwrap();
+        let divided = board.divide(2);
+        assert_eq!(divided.len(), 48);
+        assert_eq!(divided.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+        assert!(divided.iter().any(|(m, _)| m == "e1g1"));
+        assert!(divided.iter().any(|(m, _)| m == "e1c1"));
+    }
+}
@@ -0,0 +1,369 @@
+use std::fmt::{Display, Formatter, self};
+use std::iter::Peekable;
+use std::str::Chars;
+use super::board::*;
+use super::entities::*;
+use super::fen::*;
+use super::game_status::*;
+use super::move_list::*;
+use super::san::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnError {
+    /// (tag line)
+    BadTag(String),
+    BadFen(FenError),
+    /// (fullmove number, player to move, reason)
+    BadMove(u32, Player, SanError),
+    /// (opening character of the comment or variation)
+    Unterminated(char),
+    /// (token)
+    UnexpectedToken(String)
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            PgnError::BadTag(line) => write!(f, "Tag pairs must look like [Name \"Value\"], found {}", line),
+            PgnError::BadFen(e) => write!(f, "Bad FEN tag: {}", e),
+            PgnError::BadMove(fullmove_number, player, e) => {
+                let dots = if *player == Player::White { "." } else { "..." };
+                write!(f, "Move {}{}: {}", fullmove_number, dots, e)
+            },
+            PgnError::Unterminated(c) => write!(f, "'{}' is never closed", c),
+            PgnError::UnexpectedToken(token) => write!(f, "Unexpected '{}' in the moves", token)
+        }
+    }
+}
+
+/// The first game of a PGN file
+pub struct PgnGame {
+    /// (name, value), in file order
+    pub tags: Vec<(String, String)>,
+    /// Position before the first move, from the FEN tag if there is one
+    pub start: Board,
+    pub moves: Vec<MoveWithEval>,
+    /// 1-0, 0-1, 1/2-1/2 or *
+    pub result: String
+}
+
+static RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Tags every PGN game has, in export order, with their values when unknown
+static SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"), ("Site", "?"), ("Date", "????.??.??"), ("Round", "?"), ("White", "?"), ("Black", "?"), ("Result", "*")
+];
+
+static MAX_LINE_LENGTH: usize = 80;
+
+pub fn get_result_string(status: GameStatus) -> &'static str {
+    match status {
+        GameStatus::Ongoing => "*",
+        GameStatus::Checkmate(Player::White) => "1-0",
+        GameStatus::Checkmate(Player::Black) => "0-1",
+        GameStatus::Stalemate | GameStatus::Draw(_) => "1/2-1/2"
+    }
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (name, quoted) = inner.split_at(inner.find(char::is_whitespace)?);
+    let quoted = quoted.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?),
+            '"' => return None,
+            _ => value.push(c)
+        }
+    }
+    Some((name.to_string(), value))
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Skips up to and including `end`
+fn skip_until(chars: &mut Peekable<Chars>, start: char, end: char) -> Result<(), PgnError> {
+    for c in chars.by_ref() {
+        if c == end {
+            return Ok(());
+        }
+    }
+    Err(PgnError::Unterminated(start))
+}
+
+/// Skips a variation after its opening bracket, including nested variations and comments
+fn skip_variation(chars: &mut Peekable<Chars>) -> Result<(), PgnError> {
+    let mut depth = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            },
+            '{' => skip_until(chars, '{', '}')?,
+            ';' => { let _ = skip_until(chars, ';', '\n'); },
+            _ => {}
+        }
+    }
+    Err(PgnError::Unterminated('('))
+}
+
+/// Strips move numbers like 12. or 12... which may be attached to the move
+fn strip_move_number(token: &str) -> &str {
+    let digits_end = token.find(|c: char| !c.is_ascii_digit()).unwrap_or(token.len());
+    if digits_end > 0 && token[digits_end..].starts_with('.') {
+        token[digits_end..].trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+/// Reads the tag pairs and moves of the first game. Variations and comments are skipped
+pub fn read_pgn(pgn: &str) -> Result<PgnGame, PgnError> {
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    let mut in_tags = true;
+    for line in pgn.lines() {
+        let trimmed = line.trim();
+        // Escaped lines are for other programs
+        if line.starts_with('%') {
+            continue;
+        }
+        if in_tags && trimmed.starts_with('[') {
+            tags.push(parse_tag(trimmed).ok_or_else(|| PgnError::BadTag(trimmed.to_string()))?);
+        } else if !(in_tags && trimmed.is_empty()) {
+            in_tags = false;
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    let start = match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => Board::from_fen(fen).map_err(PgnError::BadFen)?,
+        None => Board::new()
+    };
+    let mut board = start.clone();
+    let mut moves = Vec::new();
+    let mut result = None;
+
+    let mut chars = movetext.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => skip_until(&mut chars, '{', '}')?,
+            ';' => { let _ = skip_until(&mut chars, ';', '\n'); },
+            '(' => skip_variation(&mut chars)?,
+            c if c.is_whitespace() => {},
+            _ => {
+                let mut token = c.to_string();
+                while let Some(next) = chars.peek() {
+                    if next.is_whitespace() || "{;()$".contains(*next) {
+                        break;
+                    }
+                    token.push(*next);
+                    chars.next();
+                }
+
+                if RESULTS.contains(&token.as_str()) {
+                    result = Some(token);
+                    break;
+                }
+                // Numeric annotation glyphs
+                if token.starts_with('$') {
+                    continue;
+                }
+                if token == ")" {
+                    return Err(PgnError::UnexpectedToken(token));
+                }
+                let san = strip_move_number(&token);
+                if san.is_empty() {
+                    continue;
+                }
+                let m = board.parse_san(san)
+                    .map_err(|e| PgnError::BadMove(board.get_fullmove_number(), board.get_player_with_turn(), e))?;
+                board.handle_move(&m);
+                moves.push(m);
+            }
+        }
+    }
+
+    let result = result
+        .or_else(|| tags.iter().find(|(name, _)| name == "Result").map(|(_, value)| value.clone()))
+        .unwrap_or_else(|| String::from("*"));
+    Ok(PgnGame { tags, start, moves, result })
+}
+
+/// Tags missing from the seven tag roster get their unknown values. The result tag always matches `result`,
+/// SetUp and FEN tags are written for non standard start positions, and a Variant tag for Chess960 ones.
+pub fn write_pgn(tags: &[(String, String)], start: &Board, sans: &[&str], result: &str) -> String {
+    let mut pgn = String::new();
+    let get_tag = |name: &str| tags.iter().find(|(tag_name, _)| tag_name == name).map(|(_, value)| value.as_str());
+    for (name, unknown) in SEVEN_TAG_ROSTER.iter() {
+        let value = if *name == "Result" { result } else { get_tag(name).unwrap_or(unknown) };
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+    }
+    let start_fen = start.to_fen();
+    let is_standard_start = start_fen == Board::new().to_fen();
+    let is_chess960 = start.is_chess960();
+    if is_chess960 {
+        pgn.push_str("[Variant \"Chess960\"]\n");
+    }
+    if !is_standard_start {
+        pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", start_fen));
+    }
+    for (name, value) in tags.iter() {
+        let is_written = SEVEN_TAG_ROSTER.iter().any(|(roster_name, _)| roster_name == name) || name == "SetUp" || name == "FEN"
+            || (is_chess960 && name == "Variant");
+        if !is_written {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+        }
+    }
+    pgn.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut fullmove_number = start.get_fullmove_number();
+    let mut player = start.get_player_with_turn();
+    for (i, san) in sans.iter().enumerate() {
+        if player == Player::White {
+            tokens.push(format!("{}.", fullmove_number));
+        } else if i == 0 {
+            tokens.push(format!("{}...", fullmove_number));
+        }
+        tokens.push(san.to_string());
+        if player == Player::Black {
+            fullmove_number += 1;
+        }
+        player = player.other_player();
+    }
+    tokens.push(result.to_string());
+
+    let mut line_length = 0;
+    for token in tokens.iter() {
+        if line_length != 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+            pgn.push('\n');
+            line_length = 0;
+        } else if line_length != 0 {
+            pgn.push(' ');
+            line_length += 1;
+        }
+        pgn.push_str(token);
+        line_length += token.len();
+    }
+    pgn.push('\n');
+    pgn
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    static SAMPLE_PGN: &str = r#"[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[Date "1992.11.04"]
+[Round "29"]
+[White "Fischer, Robert J."]
+[Black "Spassky, Boris V."]
+[Result "1/2-1/2"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 {This opening is called the Ruy Lopez.}
+4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7
+11. c4 c6 12. cxb5 axb5 13. Nc3 Bb7 14. Bg5 b4 15. Nb1 h6 16. Bh4 c5 17. dxe5
+Nxe4 18. Bxe7 Qxe7 19. exd6 Qf6 20. Nbd2 Nxd6 21. Nc4 Nxc4 22. Bxc4 Nb6
+23. Ne5 Rae8 24. Bxf7+ Rxf7 25. Nxf7 Rxe1+ 26. Qxe1 Kxf7 27. Qe3 Qg5 28. Qxg5
+hxg5 29. b3 Ke6 30. a3 Kd6 31. axb4 cxb4 32. Ra5 Nd5 33. f3 Bc8 34. Kf2 Bf5
+35. Ra7 g6 36. Ra6+ Kc5 37. Ke1 Nf4 38. g3 Nxh3 39. Kd2 Kb5 40. Rd6 Kc5 41. Ra6
+Nf2 42. g4 Bd3 43. Re6 1/2-1/2
+"#;
+
+    fn get_sans(game: &PgnGame) -> Vec<String> {
+        let mut board = game.start.clone();
+        game.moves.iter().map(|m| {
+            let san = board.get_san(m);
+            board.handle_move(m);
+            san
+        }).collect()
+    }
+
+    #[test]
+    fn reads_games() {
+        let game = read_pgn(SAMPLE_PGN).unwrap();
+        assert_eq!(game.tags.len(), 7);
+        assert_eq!(game.tags[4], (String::from("White"), String::from("Fischer, Robert J.")));
+        assert_eq!(game.moves.len(), 85);
+        assert_eq!(game.result, "1/2-1/2");
+
+        let mut board = game.start.clone();
+        for m in game.moves.iter() {
+            board.handle_move(m);
+        }
+        assert_eq!(board.to_fen(), "8/8/4R1p1/2k3p1/1p4P1/1P1b1P2/3K1n2/8 b - - 2 43");
+    }
+
+    #[test]
+    fn skips_annotations() {
+        let pgn = "1.e4 $1 {best by test} e5!? (1...c5 2.Nf3 (2.c3) {Sicilian}) ; rest of line\n%escaped line\n2.Nf3 Nc6 3... *";
+        let game = read_pgn(pgn).unwrap();
+        assert_eq!(get_sans(&game), vec!["e4", "e5", "Nf3", "Nc6"]);
+        assert_eq!(game.result, "*");
+    }
+
+    #[test]
+    fn round_trips_games() {
+        let game = read_pgn(SAMPLE_PGN).unwrap();
+        let sans = get_sans(&game);
+        let sans: Vec<&str> = sans.iter().map(|s| s.as_str()).collect();
+        let written = write_pgn(&game.tags, &game.start, &sans, &game.result);
+        assert!(written.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert!(written.starts_with("[Event \"F/S Return Match\"]\n"));
+        assert!(written.ends_with("43. Re6 1/2-1/2\n"));
+
+        let reread = read_pgn(&written).unwrap();
+        assert_eq!(reread.tags, game.tags);
+        assert_eq!(get_sans(&reread), get_sans(&game));
+    }
+
+    #[test]
+    fn writes_setup_positions() {
+        let start = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 30").unwrap();
+        let tags = vec![(String::from("White"), String::from("A \"quoted\" name")), (String::from("Annotator"), String::from("x"))];
+        let written = write_pgn(&tags, &start, &["Kd7", "e4"], "*");
+        assert!(written.contains("[White \"A \\\"quoted\\\" name\"]\n"));
+        assert!(written.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 30\"]\n[Annotator \"x\"]\n"));
+        assert!(written.ends_with("\n30... Kd7 31. e4 *\n"));
+
+        let game = read_pgn(&written).unwrap();
+        assert_eq!(game.tags[4].1, "A \"quoted\" name");
+        assert_eq!(game.start.to_fen(), start.to_fen());
+        assert_eq!(get_sans(&game), vec!["Kd7", "e4"]);
+    }
+
+    #[test]
+    fn writes_chess960_variant() {
+        let start = Board::new_chess960(0);
+        let tags = vec![(String::from("Variant"), String::from("chess 960"))];
+        let written = write_pgn(&tags, &start, &["g3"], "*");
+        assert!(written.contains("[Variant \"Chess960\"]\n[SetUp \"1\"]\n[FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1\"]\n\n"));
+        assert!(!written.contains("chess 960"));
+        assert_eq!(read_pgn(&written).unwrap().start.to_fen(), start.to_fen());
+
+        assert!(!write_pgn(&[], &Board::new(), &[], "*").contains("Variant"));
+    }
+
+    #[test]
+    fn rejects_bad_pgn() {
+        assert_eq!(read_pgn("[White Fischer]\n\n1. e4 *").err(), Some(PgnError::BadTag(String::from("[White Fischer]"))));
+        assert_eq!(read_pgn("1. e4 {unclosed").err(), Some(PgnError::Unterminated('{')));
+        assert_eq!(read_pgn("1. e4 (1. d4").err(), Some(PgnError::Unterminated('(')));
+        assert_eq!(read_pgn("1. e4 e5 2. Ke3 *").err(),
+            Some(PgnError::BadMove(2, Player::White, SanError::NoMatchingMove(String::from("Ke3")))));
+        assert!(read_pgn("[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n\n*").is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use std::fmt::{Display, Formatter, self};
+use super::board::*;
+use super::coords::*;
+use super::entities::*;
+use super::move_list::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PositionError {
+    /// (player, kings found)
+    WrongKingCount(Player, usize),
+    /// (pawn location)
+    PawnOnBackRank(Coord),
+    /// (player in check, who doesn't have the turn)
+    OpponentInCheck(Player),
+    /// (player, castle type)
+    CastleRightWithoutKing(Player, CastleType),
+    /// (player, castle type, rook start x)
+    CastleRightWithoutRook(Player, CastleType, u8),
+    /// (en passant square)
+    BadEnPassant(Coord)
+}
+
+fn castle_type_name(castle_type: CastleType) -> &'static str {
+    if castle_type == CastleType::Oo { "Kingside" } else { "Queenside" }
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            PositionError::WrongKingCount(player, n) => write!(f, "{:?} must have exactly 1 king, found {}", player, n),
+            PositionError::PawnOnBackRank(coord) => write!(f, "Pawn on {} can't be on the first or last rank", coord),
+            PositionError::OpponentInCheck(player) => write!(f, "{:?} is in check, but it's {:?}'s turn", player, player.other_player()),
+            PositionError::CastleRightWithoutKing(player, castle_type) =>
+                write!(f, "{} castle right for {:?} needs the king on its first rank", castle_type_name(*castle_type), player),
+            PositionError::CastleRightWithoutRook(player, castle_type, rook_x) =>
+                write!(f, "{} castle right for {:?} needs a rook on {}, on the castle side of the king",
+                    castle_type_name(*castle_type), player, Coord(*rook_x, player.first_row())),
+            PositionError::BadEnPassant(coord) =>
+                write!(f, "En passant square {} must be blank, behind a pawn of the player without the turn which just moved 2 squares", coord)
+        }
+    }
+}
+
+/// Position setup for the frontend. Squares, turn and rights can be changed in any order, and are only
+/// checked for legality by `validate` and `to_board`.
+#[derive(Clone)]
+pub struct PositionEditor {
+    d: [Square; 64],
+    player_with_turn: Player,
+    /// Start x of the castling rook, if the castle right is available. Index: `Player` enum number, then `CastleType` enum number
+    castle_rook_x: [[Option<u8>; 2]; 2],
+    en_passant: Option<FastCoord>
+}
+
+impl PositionEditor {
+
+    /// Empty board, white to move, no castle rights
+    pub fn new() -> Self {
+        Self {
+            d: [Square::Blank; 64],
+            player_with_turn: Player::White,
+            castle_rook_x: [[None; 2]; 2],
+            en_passant: None
+        }
+    }
+
+    pub fn from_board(board: &Board) -> Self {
+        let mut editor = Self::new();
+        for i in 0..64u8 {
+            editor.d[i as usize] = *board.get_by_index(i);
+        }
+        editor.player_with_turn = board.get_player_with_turn();
+        for player in [Player::White, Player::Black].iter() {
+            let player_state = board.get_player_state(*player);
+            for castle_type in [CastleType::Oo, CastleType::Ooo].iter() {
+                if !player_state.moved_castle_piece[*castle_type as usize] {
+                    editor.castle_rook_x[*player as usize][*castle_type as usize] = Some(player_state.rook_start_x[*castle_type as usize]);
+                }
+            }
+        }
+        editor.en_passant = board.get_en_passant();
+        editor
+    }
+
+    #[inline]
+    pub fn get_by_xy(&self, x: u8, y: u8) -> &Square {
+        &self.d[y as usize * 8 + x as usize]
+    }
+
+    #[inline]
+    pub fn set_by_xy(&mut self, x: u8, y: u8, s: Square) {
+        self.d[y as usize * 8 + x as usize] = s;
+    }
+
+    /// Removes every piece, the turn and rights are unchanged
+    pub fn clear(&mut self) {
+        self.d = [Square::Blank; 64];
+    }
+
+    pub fn set_player_with_turn(&mut self, player: Player) {
+        self.player_with_turn = player;
+    }
+
+    pub fn get_castle_right(&self, player: Player, castle_type: CastleType) -> Option<u8> {
+        self.castle_rook_x[player as usize][castle_type as usize]
+    }
+
+    /// `rook_x` is the start x of the castling rook, which only differs from standard chess in Chess960. None removes the right
+    pub fn set_castle_right(&mut self, player: Player, castle_type: CastleType, rook_x: Option<u8>) {
+        self.castle_rook_x[player as usize][castle_type as usize] = rook_x;
+    }
+
+    /// The square skipped over by the pawn which just moved, or None
+    pub fn set_en_passant(&mut self, en_passant: Option<FastCoord>) {
+        self.en_passant = en_passant;
+    }
+
+    fn find_king_indices(&self, player: Player) -> Vec<u8> {
+        (0..64u8).filter(|i| self.d[*i as usize] == Square::Occupied(Piece::King, player)).collect()
+    }
+
+    /// Everything except checks, which need a `Board`
+    fn validate_placement_and_rights(&self) -> Result<(), PositionError> {
+        for player in [Player::White, Player::Black].iter() {
+            let king_count = self.find_king_indices(*player).len();
+            if king_count != 1 {
+                return Err(PositionError::WrongKingCount(*player, king_count));
+            }
+        }
+
+        for y in [0, 7].iter() {
+            for x in 0..8 {
+                if let Square::Occupied(Piece::Pawn, _) = self.get_by_xy(x, *y) {
+                    return Err(PositionError::PawnOnBackRank(Coord(x, *y)));
+                }
+            }
+        }
+
+        for player in [Player::White, Player::Black].iter() {
+            let Coord(king_x, king_y) = FastCoord(self.find_king_indices(*player)[0]).to_coord();
+            let y = player.first_row();
+            for castle_type in [CastleType::Oo, CastleType::Ooo].iter() {
+                if let Some(rook_x) = self.get_castle_right(*player, *castle_type) {
+                    if king_y != y {
+                        return Err(PositionError::CastleRightWithoutKing(*player, *castle_type));
+                    }
+                    let is_castle_side = if *castle_type == CastleType::Oo { rook_x > king_x } else { rook_x < king_x };
+                    if rook_x >= 8 || !is_castle_side || *self.get_by_xy(rook_x, y) != Square::Occupied(Piece::Rook, *player) {
+                        return Err(PositionError::CastleRightWithoutRook(*player, *castle_type, rook_x));
+                    }
+                }
+            }
+        }
+
+        if let Some(en_passant) = self.en_passant {
+            let coord = en_passant.to_coord();
+            // The pawn which just moved belongs to the player without the turn
+            let moved_player = self.player_with_turn.other_player();
+            let (expected_y, pawn_y, pawn_start_y) = if moved_player == Player::White { (5, 4, 6) } else { (2, 3, 1) };
+            if coord.1 != expected_y ||
+                *self.get_by_xy(coord.0, coord.1) != Square::Blank ||
+                *self.get_by_xy(coord.0, pawn_start_y) != Square::Blank ||
+                *self.get_by_xy(coord.0, pawn_y) != Square::Occupied(Piece::Pawn, moved_player) {
+                return Err(PositionError::BadEnPassant(coord));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Precondition: `validate_placement_and_rights` passed
+    fn build_board(&self) -> Board {
+        let mut board = Board::empty();
+        for i in 0..64u8 {
+            board.set_by_index(i, self.d[i as usize]);
+        }
+        board.set_player_with_turn(self.player_with_turn);
+
+        for player in [Player::White, Player::Black].iter() {
+            let king_x = self.find_king_indices(*player)[0] % 8;
+            let mut rook_start_x = board.get_player_state(*player).rook_start_x;
+            for castle_type in [CastleType::Oo, CastleType::Ooo].iter() {
+                match self.get_castle_right(*player, *castle_type) {
+                    Some(rook_x) => {
+                        rook_start_x[*castle_type as usize] = rook_x;
+                        board.set_moved_castle_piece(*player, *castle_type, false);
+                    },
+                    None => board.set_moved_castle_piece(*player, *castle_type, true)
+                }
+            }
+            board.set_castle_files(*player, king_x, rook_start_x);
+        }
+
+        board.set_en_passant(self.en_passant);
+        board.refresh_king_locations();
+        board.refresh_hash();
+        board
+    }
+
+    /// Rejects positions which can't be reached or played from. Move counters aren't edited, a new position starts at 0 and 1
+    pub fn validate(&self) -> Result<(), PositionError> {
+        self.to_board().map(|_| ())
+    }
+
+    pub fn to_board(&self) -> Result<Board, PositionError> {
+        self.validate_placement_and_rights()?;
+        let board = self.build_board();
+
+        let opponent = self.player_with_turn.other_player();
+        if board.is_checking(self.player_with_turn) {
+            return Err(PositionError::OpponentInCheck(opponent));
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn editor_from_fen(fen: &str) -> PositionEditor {
+        PositionEditor::from_board(&Board::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn round_trips_boards() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 0 1"
+        ].iter() {
+            let board = Board::from_fen(fen).unwrap();
+            let rebuilt = PositionEditor::from_board(&board).to_board().unwrap();
+            assert_eq!(rebuilt.to_fen(), *fen);
+            assert_eq!(rebuilt.get_hash(), board.get_hash());
+            rebuilt.assert_piece_locs();
+        }
+    }
+
+    #[test]
+    fn builds_edited_position() {
+        let mut editor = PositionEditor::new();
+        editor.set_by_xy(4, 7, Square::Occupied(Piece::King, Player::White));
+        editor.set_by_xy(7, 7, Square::Occupied(Piece::Rook, Player::White));
+        editor.set_by_xy(4, 0, Square::Occupied(Piece::King, Player::Black));
+        editor.set_castle_right(Player::White, CastleType::Oo, Some(7));
+        editor.set_player_with_turn(Player::Black);
+
+        let board = editor.to_board().unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/4K2R b K - 0 1");
+        assert_eq!(board.get_player_state(Player::White).king_location._lsb_to_index(), 60);
+    }
+
+    #[test]
+    fn rejects_impossible_positions() {
+        let cases = [
+            ("8/8/8/8/8/8/8/4K3", PositionError::WrongKingCount(Player::Black, 0)),
+            ("4k3/8/8/8/8/8/8/3KK3", PositionError::WrongKingCount(Player::White, 2)),
+            ("4k3/8/8/8/8/8/8/4K2p", PositionError::PawnOnBackRank(Coord(7, 7))),
+            ("P3k3/8/8/8/8/8/8/4K3", PositionError::PawnOnBackRank(Coord(0, 0))),
+            ("4k3/8/8/8/8/8/8/4R1K1", PositionError::OpponentInCheck(Player::Black))
+        ];
+        for (placement, expected) in cases.iter() {
+            // FEN parsing rejects some of these already, so place the pieces by hand
+            let mut editor = PositionEditor::new();
+            for (y, row) in placement.split('/').enumerate() {
+                let mut x = 0;
+                for c in row.chars() {
+                    if let Some(skip) = c.to_digit(10) {
+                        x += skip as u8;
+                    } else {
+                        let player = if c.is_ascii_uppercase() { Player::White } else { Player::Black };
+                        editor.set_by_xy(x, y as u8, Square::Occupied(Piece::from_letter(c).unwrap(), player));
+                        x += 1;
+                    }
+                }
+            }
+            assert_eq!(editor.validate(), Err(*expected), "{}", placement);
+        }
+    }
+
+    #[test]
+    fn rejects_inconsistent_rights() {
+        let mut editor = editor_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        editor.set_by_xy(7, 7, Square::Blank);
+        assert_eq!(editor.validate(), Err(PositionError::CastleRightWithoutRook(Player::White, CastleType::Oo, 7)));
+
+        let mut editor = editor_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        editor.set_castle_right(Player::Black, CastleType::Oo, Some(0));
+        assert_eq!(editor.validate(), Err(PositionError::CastleRightWithoutRook(Player::Black, CastleType::Oo, 0)));
+
+        let mut editor = editor_from_fen("r3k2r/8/8/8/8/8/4K3/R6R w kq - 0 1");
+        editor.set_castle_right(Player::White, CastleType::Ooo, Some(0));
+        assert_eq!(editor.validate(), Err(PositionError::CastleRightWithoutKing(Player::White, CastleType::Ooo)));
+
+        let mut editor = editor_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1");
+        editor.set_en_passant(Some(FastCoord::from_xy(3, 2)));
+        assert!(editor.validate().is_ok());
+        editor.set_en_passant(Some(FastCoord::from_xy(4, 2)));
+        assert_eq!(editor.validate(), Err(PositionError::BadEnPassant(Coord(4, 2))));
+        editor.set_en_passant(Some(FastCoord::from_xy(3, 2)));
+        editor.set_player_with_turn(Player::Black);
+        assert_eq!(editor.validate(), Err(PositionError::BadEnPassant(Coord(3, 2))));
+    }
+}
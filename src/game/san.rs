@@ -0,0 +1,261 @@
+use std::fmt::{Display, Formatter, self};
+use super::board::*;
+use super::coords::*;
+use super::entities::*;
+use super::move_list::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    /// (input)
+    Malformed(String),
+    /// (input)
+    NoMatchingMove(String),
+    /// (input)
+    AmbiguousMove(String)
+}
+
+impl Display for SanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SanError::Malformed(s) => write!(f, "'{}' is not a move in standard algebraic notation, eg. e4, Nbd7, exd5, O-O or e8=Q", s),
+            SanError::NoMatchingMove(s) => write!(f, "'{}' is not a legal move in this position", s),
+            SanError::AmbiguousMove(s) => write!(f, "'{}' matches more than one legal move, add the start file or rank", s)
+        }
+    }
+}
+
+/// (from, to, promotion piece) of non castle moves
+fn get_from_to(m: &MoveWithEval) -> Option<(FastCoord, FastCoord, Option<Piece>)> {
+    match m.description() {
+        MoveDescription::NormalMove(from, to) => Some((*from, *to, None)),
+        MoveDescription::Promotion(from, to, promote_to) => Some((*from, *to, Some(*promote_to))),
+        _ => None
+    }
+}
+
+/// Parsed SAN, except for castles. Disambiguation fields are None if not given
+struct SanParts {
+    piece: Piece,
+    from_x: Option<u8>,
+    from_y: Option<u8>,
+    to: FastCoord,
+    promote_to: Option<Piece>
+}
+
+/// Capture markers and '=' are optional
+fn parse_san_parts(san: &str) -> Option<SanParts> {
+    let mut chars: Vec<char> = san.chars().filter(|c| *c != 'x' && *c != '=').collect();
+
+    let piece = match chars.first() {
+        Some(c) if "NBRQK".contains(*c) => {
+            let piece = Piece::from_letter(*c);
+            chars.remove(0);
+            piece?
+        },
+        _ => Piece::Pawn
+    };
+    let promote_to = match chars.last() {
+        Some(c) if "NBRQ".contains(*c) && piece == Piece::Pawn => {
+            let promote_to = Piece::from_letter(*c);
+            chars.pop();
+            Some(promote_to?)
+        },
+        _ => None
+    };
+
+    if chars.len() < 2 || chars.len() > 4 {
+        return None;
+    }
+    let (disambiguation, dest) = chars.split_at(chars.len() - 2);
+    let to = file_rank_to_xy_safe(dest[0], dest[1].to_digit(10)? as u8).ok()?;
+
+    let mut from_x = None;
+    let mut from_y = None;
+    for c in disambiguation.iter() {
+        if ('a'..='h').contains(c) && from_x.is_none() && from_y.is_none() {
+            from_x = Some(*c as u8 - b'a');
+        } else if ('1'..='8').contains(c) && from_y.is_none() {
+            from_y = Some(8 - c.to_digit(10)? as u8);
+        } else {
+            return None;
+        }
+    }
+
+    Some(SanParts { piece, from_x, from_y, to: FastCoord::from_coord(&to), promote_to })
+}
+
+impl Board {
+
+    fn get_legal_moves(&self) -> Vec<MoveWithEval> {
+        let mut moves = MoveList::new(100);
+        self.get_moves(&mut moves);
+        moves.v()[0..moves.write_index].to_vec()
+    }
+
+    /// Standard algebraic notation, eg. Nbd7, exd5, O-O-O, e8=N+. Precondition: `m` is legal
+    pub fn get_san(&mut self, m: &MoveWithEval) -> String {
+        let mut san = self.get_san_without_suffix(m, &self.get_legal_moves());
+
+        let revertable = self.handle_move(m);
+        if self.is_checking(self.get_player_with_turn().other_player()) {
+            san.push(if self.get_legal_moves().is_empty() { '#' } else { '+' });
+        }
+        self.revert_move(&revertable);
+        san
+    }
+
+    fn get_san_without_suffix(&self, m: &MoveWithEval, legal_moves: &[MoveWithEval]) -> String {
+        let (from, to, promote_to) = match m.description() {
+            MoveDescription::Castle(CastleType::Oo) => return String::from("O-O"),
+            MoveDescription::Castle(CastleType::Ooo) => return String::from("O-O-O"),
+            MoveDescription::SkipMove => return String::from("--"),
+            _ => get_from_to(m).unwrap()
+        };
+        let piece = match self.get_by_index(from.0) {
+            Square::Occupied(piece, _) => *piece,
+            Square::Blank => Piece::Pawn
+        };
+        let Coord(from_x, from_y) = from.to_coord();
+        let (file, rank) = xy_to_file_rank(from_x, from_y);
+        let is_capture = self.is_capture(m) || self.is_en_passant_capture(m);
+
+        let mut san = String::new();
+        if piece == Piece::Pawn {
+            if is_capture {
+                san.push(file);
+            }
+        } else {
+            san.push(piece.to_letter());
+
+            // Other pieces of the same type which can move to the same square
+            let others: Vec<Coord> = legal_moves.iter()
+                .filter_map(get_from_to)
+                .filter(|(other_from, other_to, _)| *other_to == to && *other_from != from && *self.get_by_index(other_from.0) == *self.get_by_index(from.0))
+                .map(|(other_from, _, _)| other_from.to_coord())
+                .collect();
+            if !others.is_empty() {
+                if others.iter().all(|c| c.0 != from_x) {
+                    san.push(file);
+                } else if others.iter().all(|c| c.1 != from_y) {
+                    san.push_str(&rank.to_string());
+                } else {
+                    san.push(file);
+                    san.push_str(&rank.to_string());
+                }
+            }
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&to.to_string());
+        if let Some(promote_to) = promote_to {
+            san.push('=');
+            san.push(promote_to.to_letter());
+        }
+        san
+    }
+
+    /// Inverse of `get_san`. Also accepts missing capture markers, promotions without '=', 0-0 castles,
+    /// unneeded disambiguation, and trailing annotations like ! and ?
+    pub fn parse_san(&self, san: &str) -> Result<MoveWithEval, SanError> {
+        let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let legal_moves = self.get_legal_moves();
+
+        let castle_type = match trimmed {
+            "O-O" | "0-0" => Some(CastleType::Oo),
+            "O-O-O" | "0-0-0" => Some(CastleType::Ooo),
+            _ => None
+        };
+        let candidates: Vec<&MoveWithEval> = if let Some(castle_type) = castle_type {
+            legal_moves.iter().filter(|m| *m.description() == MoveDescription::Castle(castle_type)).collect()
+        } else {
+            let parts = parse_san_parts(trimmed).ok_or_else(|| SanError::Malformed(san.to_string()))?;
+            // Pawn captures always name the start file
+            let from_x = if parts.piece == Piece::Pawn && parts.from_x.is_none() { Some(parts.to.to_coord().0) } else { parts.from_x };
+            legal_moves.iter().filter(|m| {
+                match get_from_to(m) {
+                    Some((from, to, promote_to)) => {
+                        let Coord(x, y) = from.to_coord();
+                        to == parts.to && promote_to == parts.promote_to &&
+                            matches!(self.get_by_index(from.0), Square::Occupied(piece, _) if *piece == parts.piece) &&
+                            from_x.unwrap_or(x) == x &&
+                            parts.from_y.unwrap_or(y) == y
+                    },
+                    None => false
+                }
+            }).collect()
+        };
+
+        match candidates.len() {
+            0 => Err(SanError::NoMatchingMove(san.to_string())),
+            1 => Ok(candidates[0].clone()),
+            _ => Err(SanError::AmbiguousMove(san.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn get_san(fen: &str, move_san: &str) -> String {
+        let mut board = Board::from_fen(fen).unwrap();
+        let m = board.parse_san(move_san).unwrap();
+        board.get_san(&m)
+    }
+
+    #[test]
+    fn formats_san() {
+        // (FEN, SAN, which is also parsed)
+        let cases = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Nf3"),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e4"),
+            ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2", "exd5"),
+            ("r1bqkbnr/pppppppp/2n5/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", "Nb4"),
+            ("rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", "Nbd7"),
+            ("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1", "R1a2"),
+            ("6k1/8/8/8/Q6Q/8/8/Q3K3 w - - 0 1", "Qa4d4"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "O-O"),
+            ("r3k2r/8/8/8/8/8/8/3K4 b kq - 0 1", "O-O-O+"),
+            ("8/4P3/8/8/8/k7/8/K7 w - - 0 1", "e8=N"),
+            ("3k4/4P3/8/8/8/8/8/K7 w - - 0 1", "e8=Q+"),
+            ("3q1k2/4P3/8/8/8/8/8/K7 w - - 0 1", "exd8=R+"),
+            ("6k1/5ppp/8/8/8/8/8/R6K w - - 0 1", "Ra8#"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "exd6")
+        ];
+        for (fen, san) in cases.iter() {
+            assert_eq!(get_san(fen, san), *san, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn parses_lenient_san() {
+        let fen = "r1bqkbnr/pppppppp/2n5/8/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 0 1";
+        let cases = [("Ng1", "Ng1"), ("Nf3g1", "Ng1"), ("e2e4", "e4"), ("Nf3-e5", ""), ("e4!?", "e4")];
+        for (input, expected) in cases.iter() {
+            let mut board = Board::from_fen(fen).unwrap();
+            match board.parse_san(input) {
+                Ok(m) => assert_eq!(board.get_san(&m), *expected, "{}", input),
+                Err(e) => assert!(expected.is_empty(), "{}: {}", input, e)
+            }
+        }
+
+        let board = Board::from_fen("3q1k2/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert!(board.parse_san("exd8Q").is_ok());
+        assert!(board.parse_san("0-0").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_san() {
+        let board = Board::from_fen("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(board.parse_san("Ra2").err(), Some(SanError::AmbiguousMove(String::from("Ra2"))));
+        assert_eq!(board.parse_san("Rb8").err(), Some(SanError::NoMatchingMove(String::from("Rb8"))));
+        assert_eq!(board.parse_san("Ra9").err(), Some(SanError::Malformed(String::from("Ra9"))));
+        assert_eq!(board.parse_san("hello").err(), Some(SanError::Malformed(String::from("hello"))));
+        assert_eq!(board.parse_san("").err(), Some(SanError::Malformed(String::new())));
+        // Pawn captures need the start file
+        let board = Board::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2").unwrap();
+        assert_eq!(board.parse_san("d5").err(), Some(SanError::NoMatchingMove(String::from("d5"))));
+    }
+}
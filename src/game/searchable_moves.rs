@@ -1,11 +1,12 @@
 use std::collections::HashMap;
 use super::coords::*;
 use super::move_list::*;
+use super::board::*;
 use super::super::*;
 
-/// (src, dest)
+/// (src, dest, promotion piece if any)
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
-struct SearchableMoveKey(FastCoord, FastCoord);
+struct SearchableMoveKey(FastCoord, FastCoord, Option<Piece>);
 
 pub struct SearchableMoves {
     map: HashMap<SearchableMoveKey, MoveWithEval>
@@ -17,20 +18,25 @@ impl SearchableMoves {
         SearchableMoves { map }
     }
 
-    pub fn reset(&mut self, curr_player: Player, move_list: &MoveList, start: usize, end_exclusive: usize) {
+    pub fn reset(&mut self, board: &Board, move_list: &MoveList, start: usize, end_exclusive: usize) {
 
         self.map.clear();
+        let curr_player = board.get_player_with_turn();
 
         for i in start..end_exclusive {
             let m = &move_list.v()[i];
 
             match m.description() {
                 MoveDescription::NormalMove(from, to) => {
-                    self.map.insert(SearchableMoveKey(*from, *to), m.clone());
+                    self.map.insert(SearchableMoveKey(*from, *to, None), m.clone());
+                }
+                MoveDescription::Promotion(from, to, promote_to) => {
+                    self.map.insert(SearchableMoveKey(*from, *to, Some(*promote_to)), m.clone());
                 }
                 MoveDescription::Castle(castle_type) => {
-                    for (from, to) in (CASTLE_UTILS.draggable_coords[*castle_type as usize][curr_player as usize]).iter() {
-                        self.map.insert(SearchableMoveKey(*from, *to), m.clone());
+                    // Includes king takes own rook, the only way to castle in Chess960 when the king is already next to its destination
+                    for (from, to) in board.get_castle_squares(curr_player, *castle_type).draggable_coords.iter() {
+                        self.map.insert(SearchableMoveKey(*from, *to, None), m.clone());
                     }
                 }
                 _ => {
@@ -41,10 +47,17 @@ impl SearchableMoves {
         console_log!("Searchable size - {}", self.map.len());
     }
 
-    pub fn get_move(&self, from: &Coord, to: &Coord) -> Option<&MoveWithEval> {
-        match self.map.get(&SearchableMoveKey(FastCoord::from_coord(from), FastCoord::from_coord(to))) {
+    /// `promote_to` must be provided exactly when the drag is a promotion, see `is_promotion`
+    pub fn get_move(&self, from: &Coord, to: &Coord, promote_to: Option<Piece>) -> Option<&MoveWithEval> {
+        match self.map.get(&SearchableMoveKey(FastCoord::from_coord(from), FastCoord::from_coord(to), promote_to)) {
             Some(x) => Some(x),
             None => None
         }
     }
+
+    /// Whether the drag needs a promotion choice from the player
+    pub fn is_promotion(&self, from: &Coord, to: &Coord) -> bool {
+        // All promotion pieces are always generated together, so checking one is enough
+        self.map.contains_key(&SearchableMoveKey(FastCoord::from_coord(from), FastCoord::from_coord(to), Some(Piece::Queen)))
+    }
 }
@@ -0,0 +1,148 @@
+use std::fmt::{Display, Formatter, self};
+use super::board::*;
+use super::coords::*;
+use super::entities::*;
+use super::move_list::*;
+use super::castle_utils::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UciMoveError {
+    /// (input)
+    Malformed(String),
+    /// (input)
+    IllegalMove(String)
+}
+
+impl Display for UciMoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            UciMoveError::Malformed(s) => write!(f, "'{}' is not a UCI move, eg. e2e4, e7e8q or e1g1", s),
+            UciMoveError::IllegalMove(s) => write!(f, "'{}' is not a legal move in this position", s)
+        }
+    }
+}
+
+fn parse_square(s: &str) -> Option<FastCoord> {
+    let mut chars = s.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?.to_digit(10)? as u8;
+    file_rank_to_xy_safe(file, rank).ok().map(|coord| FastCoord::from_coord(&coord))
+}
+
+impl Board {
+
+    /// Whether castles use the standard king and rook start files, which are written as the king's start and end squares.
+    /// Chess960 castles are written as the king taking its own rook instead.
+    fn has_standard_castle_files(&self, player: Player, castle_type: CastleType) -> bool {
+        let player_state = self.get_player_state(player);
+        player_state.king_start_x == STANDARD_KING_X && player_state.rook_start_x[castle_type as usize] == STANDARD_ROOK_X[castle_type as usize]
+    }
+
+    /// UCI long algebraic notation, eg. e2e4, e7e8q, e1g1, and 0000 for a skip move
+    pub fn get_uci_move(&self, m: &MoveWithEval) -> String {
+        match m.description() {
+            MoveDescription::NormalMove(from, to) => format!("{}{}", from, to),
+            MoveDescription::Promotion(from, to, promote_to) => format!("{}{}{}", from, to, promote_to.to_letter().to_ascii_lowercase()),
+            MoveDescription::Castle(castle_type) => {
+                let player = self.get_player_with_turn();
+                let king_sq = FastCoord(self.get_player_state(player).king_location._lsb_to_index());
+                let castle_squares = self.get_castle_squares(player, *castle_type);
+                if self.has_standard_castle_files(player, *castle_type) {
+                    format!("{}{}", king_sq, castle_squares.post_castle_king_sq)
+                } else {
+                    format!("{}{}", king_sq, castle_squares.rook_sq)
+                }
+            },
+            MoveDescription::SkipMove => String::from("0000")
+        }
+    }
+
+    /// Inverse of `get_uci_move`, the legal move matching `s`. Castles may also be written as the king taking its own rook
+    pub fn parse_uci_move(&self, s: &str) -> Result<MoveWithEval, UciMoveError> {
+        let s = s.trim();
+        if !s.is_ascii() || (s.len() != 4 && s.len() != 5) {
+            return Err(UciMoveError::Malformed(s.to_string()));
+        }
+        let from = parse_square(&s[0..2]).ok_or_else(|| UciMoveError::Malformed(s.to_string()))?;
+        let to = parse_square(&s[2..4]).ok_or_else(|| UciMoveError::Malformed(s.to_string()))?;
+        let promote_to = match s.chars().nth(4) {
+            Some(c) => match Piece::from_letter(c) {
+                Some(piece) if PROMOTION_PIECES.contains(&piece) && c.is_ascii_lowercase() => Some(piece),
+                _ => return Err(UciMoveError::Malformed(s.to_string()))
+            },
+            None => None
+        };
+
+        let mut moves = MoveList::new(100);
+        self.get_moves(&mut moves);
+        let legal_moves = &moves.v()[0..moves.write_index];
+
+        let expected = match promote_to {
+            Some(promote_to) => MoveDescription::Promotion(from, to, promote_to),
+            None => MoveDescription::NormalMove(from, to)
+        };
+        if let Some(m) = legal_moves.iter().find(|m| *m.description() == expected) {
+            return Ok(m.clone());
+        }
+
+        let player = self.get_player_with_turn();
+        let king_sq = FastCoord(self.get_player_state(player).king_location._lsb_to_index());
+        if promote_to.is_none() && from == king_sq {
+            for m in legal_moves.iter() {
+                if let MoveDescription::Castle(castle_type) = m.description() {
+                    let castle_squares = self.get_castle_squares(player, *castle_type);
+                    if to == castle_squares.post_castle_king_sq || to == castle_squares.rook_sq {
+                        return Ok(m.clone());
+                    }
+                }
+            }
+        }
+        Err(UciMoveError::IllegalMove(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn round_trips_legal_moves() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"
+        ].iter() {
+            let board = Board::from_fen(fen).unwrap();
+            let mut moves = MoveList::new(100);
+            board.get_moves(&mut moves);
+            for m in moves.v()[0..moves.write_index].iter() {
+                let uci = board.get_uci_move(m);
+                assert!(board.parse_uci_move(&uci).unwrap().description() == m.description(), "{} in {}", uci, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_castles() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert!(*board.parse_uci_move("e1g1").unwrap().description() == MoveDescription::Castle(CastleType::Oo));
+        assert!(*board.parse_uci_move("e1a1").unwrap().description() == MoveDescription::Castle(CastleType::Ooo));
+
+        // Chess960 with the king already on its castle destination
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/6KR w H - 0 1").unwrap();
+        let castle = board.parse_uci_move("g1h1").unwrap();
+        assert!(*castle.description() == MoveDescription::Castle(CastleType::Oo));
+        assert_eq!(board.get_uci_move(&castle), "g1h1");
+    }
+
+    #[test]
+    fn rejects_bad_moves() {
+        let board = Board::new();
+        assert_eq!(board.parse_uci_move("e2e5").err(), Some(UciMoveError::IllegalMove(String::from("e2e5"))));
+        assert_eq!(board.parse_uci_move("0000").err(), Some(UciMoveError::Malformed(String::from("0000"))));
+        assert_eq!(board.parse_uci_move("e2e4k").err(), Some(UciMoveError::Malformed(String::from("e2e4k"))));
+        assert_eq!(board.parse_uci_move("e2").err(), Some(UciMoveError::Malformed(String::from("e2"))));
+        assert_eq!(board.parse_uci_move("é2e4").err(), Some(UciMoveError::Malformed(String::from("é2e4"))));
+    }
+}
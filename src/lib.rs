@@ -2,15 +2,22 @@
 extern crate lazy_static;
 extern crate console_error_panic_hook;
 
-#[cfg(test)]
+#[cfg(not(target_arch = "wasm32"))]
 extern crate rand;
 
 mod extern_funcs;
 mod macros;
 mod game;
 mod ai;
+#[cfg(not(target_arch = "wasm32"))]
+mod engine_io;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod uci;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cecp;
 
 use ai::*;
+use ai::opening_book::*;
 use game::bitboard_presets::*;
 use game::memo::*;
 use game::coords::*;
@@ -18,7 +25,11 @@ use game::entities::*;
 use game::board::*;
 use game::castle_utils::*;
 use game::searchable_moves::*;
+use game::game_status::*;
 use game::move_list::*;
+use game::move_history::*;
+use game::pgn::*;
+use game::position_editor::*;
 use wasm_bindgen::prelude::*;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -33,12 +44,127 @@ lazy_static! {
     pub static ref BITBOARD_PRESETS: BitboardPresets = BitboardPresets::new();
 }
 
+/// Flattened `GameStatus` for the frontend
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameStatusCode {
+    Ongoing,
+    WhiteWins,
+    BlackWins,
+    Stalemate,
+    DrawByRepetition,
+    DrawByFiftyMove,
+    DrawByInsufficientMaterial
+}
+
+impl From<GameStatus> for GameStatusCode {
+    fn from(status: GameStatus) -> Self {
+        match status {
+            GameStatus::Ongoing => GameStatusCode::Ongoing,
+            GameStatus::Checkmate(Player::White) => GameStatusCode::WhiteWins,
+            GameStatus::Checkmate(Player::Black) => GameStatusCode::BlackWins,
+            GameStatus::Stalemate => GameStatusCode::Stalemate,
+            GameStatus::Draw(DrawReason::Repetition) => GameStatusCode::DrawByRepetition,
+            GameStatus::Draw(DrawReason::FiftyMove) => GameStatusCode::DrawByFiftyMove,
+            GameStatus::Draw(DrawReason::InsufficientMaterial) => GameStatusCode::DrawByInsufficientMaterial
+        }
+    }
+}
+
+/// Frontend piece numbers: `Piece` enum number + 1, negative for black, 0 for blank
+fn square_to_num(sq: &Square) -> i8 {
+    match sq {
+        Square::Occupied(piece, player) => ((*piece as u8) + 1) as i8 * player.multiplier() as i8,
+        Square::Blank => 0
+    }
+}
+
+fn num_to_square(num: i8) -> Option<Square> {
+    if num == 0 {
+        return Some(Square::Blank);
+    }
+    let player = if num > 0 { Player::White } else { Player::Black };
+    Piece::from_num(num.unsigned_abs() - 1).map(|piece| Square::Occupied(piece, player))
+}
+
+/// Position setup for the frontend, turned into a game with `Main::new_from_editor`
+#[wasm_bindgen]
+pub struct Editor {
+    editor: PositionEditor
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Editor::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Editor {
+
+    /// Empty board, white to move, no castle rights
+    pub fn new() -> Editor {
+        Editor { editor: PositionEditor::new() }
+    }
+
+    pub fn from_main(main: &Main) -> Editor {
+        Editor { editor: PositionEditor::from_board(&main.board) }
+    }
+
+    /// Same piece numbers as `Main::get_piece`, -99 if out of bounds
+    pub fn get_piece(&self, x: i32, y: i32) -> i8 {
+        if check_i32_xy(x, y).is_err() { return -99; }
+        square_to_num(self.editor.get_by_xy(x as u8, y as u8))
+    }
+
+    /// `piece` uses the same numbers as `Main::get_piece`, 0 removes the piece
+    pub fn set_piece(&mut self, x: i32, y: i32, piece: i8) -> bool {
+        if check_i32_xy(x, y).is_err() { return false; }
+        match num_to_square(piece) {
+            Some(sq) => {
+                self.editor.set_by_xy(x as u8, y as u8, sq);
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.editor.clear();
+    }
+
+    pub fn set_white_to_move(&mut self, is_white: bool) {
+        self.editor.set_player_with_turn(if is_white { Player::White } else { Player::Black });
+    }
+
+    /// `rook_x` is the start x of the castling rook, negative removes the right
+    pub fn set_castle_right(&mut self, is_white: bool, is_kingside: bool, rook_x: i32) {
+        let player = if is_white { Player::White } else { Player::Black };
+        let castle_type = if is_kingside { CastleType::Oo } else { CastleType::Ooo };
+        let rook_x = if rook_x < 0 { None } else { Some(rook_x.min(8) as u8) };
+        self.editor.set_castle_right(player, castle_type, rook_x);
+    }
+
+    /// The square skipped over by the pawn which just moved, out of bounds coordinates remove it
+    pub fn set_en_passant(&mut self, x: i32, y: i32) {
+        let en_passant = if check_i32_xy(x, y).is_ok() { Some(FastCoord::from_xy(x as u8, y as u8)) } else { None };
+        self.editor.set_en_passant(en_passant);
+    }
+
+    /// Error describes the first problem found
+    pub fn validate(&self) -> Result<(), JsValue> {
+        self.editor.validate().map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
 #[wasm_bindgen]
 pub struct Main {
     board: Board,
     ai: Ai,
+    history: MoveHistory,
+    /// (name, value) PGN tags, written by `get_pgn`
+    pgn_tags: Vec<(String, String)>,
 
-    temp: MoveList,
     move_list: MoveList,
     searchable: SearchableMoves
 }
@@ -47,49 +173,129 @@ pub struct Main {
 impl Main {
 
     pub fn new() -> Main {
+        Main::new_with_board(Board::new())
+    }
+
+    pub fn new_from_fen(fen: &str) -> Result<Main, JsValue> {
+        match Board::from_fen(fen) {
+            Ok(board) => Ok(Main::new_with_board(board)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn new_from_editor(editor: &Editor) -> Result<Main, JsValue> {
+        match editor.editor.to_board() {
+            Ok(board) => Ok(Main::new_with_board(board)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    /// Loads the first game, with its moves played so they can be undone
+    pub fn new_from_pgn(pgn: &str) -> Result<Main, JsValue> {
+        let game = read_pgn(pgn).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let mut main = Main::new_with_board(game.start);
+        for m in game.moves.iter() {
+            main.history.play(&mut main.board, m);
+        }
+        main.pgn_tags = game.tags;
+        main.set_pgn_tag("Result", &game.result);
+        Ok(main)
+    }
+
+    /// Scharnagl numbering, 518 is the standard start position
+    pub fn new_chess960(index: u16) -> Result<Main, JsValue> {
+        if index >= 960 {
+            return Err(JsValue::from_str("Chess960 position index must be below 960"));
+        }
+        Ok(Main::new_with_board(Board::new_chess960(index)))
+    }
+
+    fn new_with_board(board: Board) -> Main {
         std::panic::set_hook(Box::new(console_error_panic_hook::hook));
 
         // Initialize lazy
-        let _ = &CASTLE_UTILS.oo_sqs;
-        let _ = &CASTLE_UTILS.ooo_sqs;
-        let _ = &CASTLE_UTILS.king_traversal_coords;
-        let _ = &CASTLE_UTILS.draggable_coords;
+        let _ = &CASTLE_UTILS.pre_castle_king_sq;
         let _ = &RANDOM_NUMBER_KEYS.squares;
         let _ = &BITBOARD_PRESETS.knight_jumps;
         let _ = &BITBOARD_PRESETS.rays;
 
-        let board = Board::new();
         Main {
+            history: MoveHistory::new(&board),
             board, 
             ai: Ai::new(),
+            pgn_tags: Vec::new(),
 
-            temp: MoveList::new(50),
             move_list: MoveList::new(50),
             searchable: SearchableMoves::new()
         }
     }
 
+    /// Contents of a Polyglot `.bin` book, which the AI plays from before searching.
+    /// `weighted` chooses randomly between book moves by their weights, otherwise the highest weight move is played
+    pub fn set_opening_book(&mut self, bytes: &[u8], weighted: bool) -> Result<(), JsValue> {
+        let policy = if weighted { BookPolicy::Weighted } else { BookPolicy::Best };
+        let book = OpeningBook::from_bytes(bytes, policy).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.ai.set_book(Some(book));
+        Ok(())
+    }
+
+    pub fn clear_opening_book(&mut self) {
+        self.ai.set_book(None);
+    }
+
+    /// Transposition table size, 16 MB by default. Results are kept between AI moves, so bigger is better if memory allows
+    pub fn set_memo_size_mb(&mut self, size_mb: usize) {
+        self.ai.set_memo_size_mb(size_mb.max(1));
+    }
+
     pub fn make_ai_move(&mut self) {
-        self.ai.make_move(9, 10000, &mut self.board);
+        if self.get_game_status() != GameStatusCode::Ongoing {
+            return;
+        }
+        if let Some(m) = self.ai.find_move(SearchLimits::new(9, 10000), &self.board) {
+            self.play_move(&m);
+        }
+    }
+
+    pub fn get_game_status(&mut self) -> GameStatusCode {
+        let status = self.board.get_game_status(&mut self.move_list);
+        GameStatusCode::from(status)
     }
 
     pub fn refresh_player_moves(&mut self) {
         self.move_list.write_index = 0;
-        self.board.get_moves(&mut self.temp, &mut self.move_list);
+        self.board.get_moves(&mut self.move_list);
         let end_exclusive = self.move_list.write_index;
         console_log!("{} moves", end_exclusive);
         console_log!("White King\n{}", self.board.get_player_state(Player::White).king_location);
         console_log!("Black King\n{}", self.board.get_player_state(Player::Black).king_location);
-        self.searchable.reset(self.board.get_player_with_turn(), &mut self.move_list, 0, end_exclusive);
+        self.searchable.reset(&self.board, &self.move_list, 0, end_exclusive);
+    }
+
+    /// Whether the move needs a promotion piece passed to `try_move`
+    pub fn is_promotion_move(&self, from_x: i32, from_y: i32, to_x: i32, to_y: i32) -> bool {
+        if check_i32_xy(from_x, from_y).is_err() { return false; }
+        if check_i32_xy(to_x, to_y).is_err() { return false; }
+
+        self.searchable.is_promotion(&Coord(from_x as u8, from_y as u8), &Coord(to_x as u8, to_y as u8))
     }
 
-    pub fn try_move(&mut self, from_x: i32, from_y: i32, to_x: i32, to_y: i32) -> bool {
+    /// `promote_to` uses the same piece numbers as `get_piece` (sign is ignored), 0 if the move is not a promotion
+    pub fn try_move(&mut self, from_x: i32, from_y: i32, to_x: i32, to_y: i32, promote_to: i8) -> bool {
         if check_i32_xy(from_x, from_y).is_err() { return false; }
         if check_i32_xy(to_x, to_y).is_err() { return false; }
 
-        let _m = self.searchable.get_move(&Coord(from_x as u8, from_y as u8), &Coord(to_x as u8, to_y as u8));
+        let promote_to_piece = if promote_to == 0 {
+            None
+        } else if let Some(piece) = Piece::from_num(promote_to.unsigned_abs() - 1) {
+            Some(piece)
+        } else {
+            return false;
+        };
+
+        let _m = self.searchable.get_move(&Coord(from_x as u8, from_y as u8), &Coord(to_x as u8, to_y as u8), promote_to_piece).cloned();
         if let Some(m) = _m {
-            self.board.handle_move(m);
+            self.play_move(&m);
             self.board.assert_hash();
             true
         } else {
@@ -97,13 +303,122 @@ impl Main {
         }
     }
 
+    /// UCI long algebraic notation, eg. e2e4, e7e8q or e1g1. Call `refresh_player_moves` after
+    pub fn try_move_uci(&mut self, uci: &str) -> Result<(), JsValue> {
+        match self.board.parse_uci_move(uci) {
+            Ok(m) => {
+                self.play_move(&m);
+                Ok(())
+            },
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    /// Standard algebraic notation, eg. Nbd7, exd5, O-O or e8=Q+. Call `refresh_player_moves` after
+    pub fn try_move_san(&mut self, san: &str) -> Result<(), JsValue> {
+        match self.board.parse_san(san) {
+            Ok(m) => {
+                self.play_move(&m);
+                Ok(())
+            },
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    /// The game goes on from here, so a result set earlier, eg. imported from a PGN, no longer applies
+    fn play_move(&mut self, m: &MoveWithEval) {
+        self.history.play(&mut self.board, m);
+        self.clear_result_tag();
+    }
+
+    fn clear_result_tag(&mut self) {
+        self.pgn_tags.retain(|(name, _)| name != "Result");
+    }
+
+    /// Replaces the tag if it's set already, eg. White, Black or Event
+    pub fn set_pgn_tag(&mut self, name: &str, value: &str) {
+        match self.pgn_tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.pgn_tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    /// The game so far. The result comes from the game status if the game is over,
+    /// otherwise from the Result tag, eg. set by the frontend after a resignation
+    pub fn get_pgn(&mut self) -> String {
+        let status = self.board.get_game_status(&mut self.move_list);
+        let result_tag = self.pgn_tags.iter().find(|(name, _)| name == "Result").map(|(_, value)| value.as_str());
+        let result = match (status, result_tag) {
+            (GameStatus::Ongoing, Some(result_tag)) => result_tag,
+            _ => get_result_string(status)
+        };
+        write_pgn(&self.pgn_tags, self.history.get_start(), &self.history.get_sans(), result)
+    }
+
+    /// Space separated SAN of the moves played, oldest first
+    pub fn get_history_san(&self) -> String {
+        self.history.get_sans().join(" ")
+    }
+
+    /// Takes back the last move. Call `refresh_player_moves` after. False if there is no move to undo
+    pub fn undo(&mut self) -> bool {
+        let is_undone = self.history.undo(&mut self.board);
+        if is_undone {
+            self.clear_result_tag();
+        }
+        is_undone
+    }
+
+    /// Replays the last undone move. Call `refresh_player_moves` after. False if there is no undone move
+    pub fn redo(&mut self) -> bool {
+        let is_redone = self.history.redo(&mut self.board);
+        if is_redone {
+            self.clear_result_tag();
+        }
+        is_redone
+    }
+
+    /// Moves played since the game started, excluding undone ones
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.board.is_fifty_move_draw()
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.board.is_threefold_repetition()
+    }
+
+    pub fn is_insufficient_material(&self) -> bool {
+        self.board.is_insufficient_material()
+    }
+
+    pub fn perft(&mut self, depth: u8) -> u64 {
+        self.board.perft(depth)
+    }
+
+    /// Move generator check for the current position, one line per root move followed by the total
+    pub fn divide(&mut self, depth: u8) -> String {
+        let divided = self.board.divide(depth);
+        let total: u64 = divided.iter().map(|(_, nodes)| nodes).sum();
+        let mut result = String::new();
+        for (m, nodes) in divided.iter() {
+            result.push_str(&format!("{}: {}\n", m, nodes));
+        }
+        result.push_str(&format!("Total: {}", total));
+        result
+    }
+
+    pub fn get_fen(&self) -> String {
+        self.board.to_fen()
+    }
+
     pub fn get_piece(&self, x: i32, y: i32) -> i8 {
-        if let Ok(Square::Occupied(piece, player)) = self.board.get_by_xy_safe(x, y) {
-            ((*piece as u8) + 1) as i8 * player.multiplier() as i8
-        } else if let Ok(Square::Blank) = self.board.get_by_xy_safe(x, y) {
-            0
-        } else {
-            -99
+        match self.board.get_by_xy_safe(x, y) {
+            Ok(sq) => square_to_num(sq),
+            Err(_) => -99
         }
     }
 }
@@ -0,0 +1,329 @@
+//! UCI protocol over standard input and output, for running the engine natively in GUIs like Cute Chess
+
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use super::ai::*;
+use super::ai::opening_book::*;
+use super::ai::transposition_table::DEFAULT_SIZE_MB;
+use super::engine_io::*;
+use super::game::board::*;
+
+/// Largest Hash option, in MB
+static MAX_HASH_MB: usize = 4096;
+static MAX_THREADS: usize = 256;
+
+/// Limits from the arguments of a `go` command
+struct GoParams {
+    depth: i8,
+    max_nodes: u64,
+    move_time_ms: Option<u64>,
+    /// The best move is only sent after `stop`, even if a limit ends the search before
+    infinite: bool
+}
+
+fn parse_go(args: &[&str], board: &Board) -> GoParams {
+    let mut params = GoParams { depth: MAX_DEPTH, max_nodes: u64::MAX, move_time_ms: None, infinite: false };
+    // Index: `Player` enum number
+    let mut time_left_ms = [None, None];
+    let mut increment_ms = [0, 0];
+    let mut moves_to_go = DEFAULT_MOVES_TO_GO;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).and_then(|v| v.parse::<u64>().ok());
+        match (args[i], value) {
+            ("depth", Some(v)) => params.depth = get_searched_depth(v.min(MAX_DEPTH as u64) as i8),
+            ("nodes", Some(v)) => params.max_nodes = v,
+            ("movetime", Some(v)) => params.move_time_ms = Some(v),
+            ("wtime", Some(v)) => time_left_ms[0] = Some(v),
+            ("btime", Some(v)) => time_left_ms[1] = Some(v),
+            ("winc", Some(v)) => increment_ms[0] = v,
+            ("binc", Some(v)) => increment_ms[1] = v,
+            ("movestogo", Some(v)) => moves_to_go = v,
+            ("infinite", _) => {
+                params.infinite = true;
+                i += 1;
+                continue;
+            },
+            // Eg. ponder, which is not supported
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+
+    let player = board.get_player_with_turn() as usize;
+    if let (None, Some(time_left_ms)) = (params.move_time_ms, time_left_ms[player]) {
+        params.move_time_ms = Some(get_move_time_ms(time_left_ms, increment_ms[player], moves_to_go));
+    }
+    params
+}
+
+/// `position startpos` or `position fen <fen>`, optionally followed by `moves <uci moves>`
+fn parse_position(args: &[&str]) -> Result<Board, String> {
+    let (mut board, rest) = match args.first() {
+        Some(&"startpos") => (Board::new(), &args[1..]),
+        Some(&"fen") => {
+            let fen_end = args.iter().position(|arg| *arg == "moves").unwrap_or(args.len());
+            let board = Board::from_fen(&args[1..fen_end].join(" ")).map_err(|e| e.to_string())?;
+            (board, &args[fen_end..])
+        },
+        _ => return Err(String::from("Expected position startpos or position fen <fen>"))
+    };
+
+    if rest.first() == Some(&"moves") {
+        for uci in rest[1..].iter() {
+            let m = board.parse_uci_move(uci).map_err(|e| e.to_string())?;
+            board.handle_move(&m);
+        }
+    }
+    Ok(board)
+}
+
+fn search(ai: &mut Ai, board: &Board, params: GoParams, stop: Arc<AtomicBool>) {
+    let start = Instant::now();
+    let limits = SearchLimits { depth: params.depth, ms_till_terminate: params.move_time_ms.map_or(u128::MAX, |ms| ms as u128), max_nodes: params.max_nodes, stop: Some(stop.clone()) };
+    let best_move = ai.find_move_with_info(limits, board, |info| {
+        let ms = start.elapsed().as_millis();
+        let nps = info.nodes as u128 * 1000 / ms.max(1);
+        let score = match get_mate_in(info.score) {
+            Some(moves) => format!("mate {}", moves),
+            None => format!("cp {}", info.score)
+        };
+        send(&format!("info depth {} score {} nodes {} time {} nps {} hashfull {} pv {}",
+            info.depth, score, info.nodes, ms, nps, info.hashfull, board.get_uci_move(info.best_move)));
+    });
+    // `UciEngine::stop` unparks this thread after setting the flag
+    while params.infinite && !stop.load(Ordering::Relaxed) {
+        thread::park();
+    }
+    match best_move {
+        Some(m) => send(&format!("bestmove {}", board.get_uci_move(&m))),
+        None => send("bestmove 0000")
+    }
+}
+
+struct UciEngine {
+    board: Board,
+    /// None while a search thread has it
+    ai: Option<Ai>,
+    /// (search thread, its stop flag)
+    search: Option<(JoinHandle<Ai>, Arc<AtomicBool>)>,
+    /// Polyglot book path from the BookFile option, empty for no book
+    book_file: String,
+    book_policy: BookPolicy
+}
+
+impl UciEngine {
+
+    fn new() -> Self {
+        Self {
+            board: Board::new(),
+            ai: Some(Ai::new()),
+            search: None,
+            book_file: String::new(),
+            book_policy: BookPolicy::Weighted
+        }
+    }
+
+    /// Gives the AI the book from the options. Precondition: no search is running
+    fn load_book(&mut self) {
+        let book = if self.book_file.is_empty() {
+            None
+        } else {
+            let book = fs::read(&self.book_file)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| OpeningBook::from_bytes(&bytes, self.book_policy).map_err(|e| e.to_string()));
+            match book {
+                Ok(book) => Some(book),
+                Err(e) => {
+                    send(&format!("info string Book {}: {}", self.book_file, e));
+                    None
+                }
+            }
+        };
+        self.ai.as_mut().unwrap().set_book(book);
+    }
+
+    /// `setoption name <name> value <value>`, where names and values may contain spaces
+    fn set_option(&mut self, args: &[&str]) {
+        let value_start = args.iter().position(|arg| *arg == "value").unwrap_or(args.len());
+        let name = args[..value_start].iter().skip_while(|arg| **arg == "name").cloned().collect::<Vec<&str>>().join(" ");
+        let value = args.get(value_start + 1..).unwrap_or(&[]).join(" ");
+        self.stop();
+        match name.as_str() {
+            "Hash" => match value.parse::<usize>() {
+                Ok(size_mb) => self.ai.as_mut().unwrap().set_memo_size_mb(size_mb.clamp(1, MAX_HASH_MB)),
+                Err(_) => send(&format!("info string Bad Hash {}", value))
+            },
+            "Threads" => match value.parse::<usize>() {
+                Ok(threads) => self.ai.as_mut().unwrap().set_threads(threads.clamp(1, MAX_THREADS)),
+                Err(_) => send(&format!("info string Bad Threads {}", value))
+            },
+            "BookFile" => {
+                self.book_file = if value == "<empty>" { String::new() } else { value };
+                self.load_book();
+            },
+            "BookPolicy" => match value.as_str() {
+                "best" => {
+                    self.book_policy = BookPolicy::Best;
+                    self.load_book();
+                },
+                "weighted" => {
+                    self.book_policy = BookPolicy::Weighted;
+                    self.load_book();
+                },
+                _ => send(&format!("info string Unknown BookPolicy {}", value))
+            },
+            _ => send(&format!("info string Unknown option {}", name))
+        }
+    }
+
+    /// Stops any search, which still sends its best move
+    fn stop(&mut self) {
+        if let Some((handle, stop)) = self.search.take() {
+            stop.store(true, Ordering::Relaxed);
+            handle.thread().unpark();
+            self.ai = Some(handle.join().expect("Search thread panicked"));
+        }
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        self.stop();
+        let params = parse_go(args, &self.board);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut ai = self.ai.take().unwrap();
+        let board = self.board.clone();
+        let search_stop = stop.clone();
+        let handle = thread::Builder::new()
+            .stack_size(SEARCH_STACK_SIZE)
+            .spawn(move || {
+                search(&mut ai, &board, params, search_stop);
+                ai
+            })
+            .expect("Failed to start search thread");
+        self.search = Some((handle, stop));
+    }
+
+    /// False when the engine should quit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first() {
+            Some(&"uci") => {
+                send(&format!("id name {}", ENGINE_NAME));
+                send(&format!("id author {}", ENGINE_AUTHOR));
+                send(&format!("option name Hash type spin default {} min 1 max {}", DEFAULT_SIZE_MB, MAX_HASH_MB));
+                send(&format!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS));
+                send("option name BookFile type string default <empty>");
+                send("option name BookPolicy type combo default weighted var weighted var best");
+                send("uciok");
+            },
+            Some(&"isready") => send("readyok"),
+            Some(&"setoption") => self.set_option(&words[1..]),
+            Some(&"ucinewgame") => {
+                self.stop();
+                self.board = Board::new();
+                self.ai.as_mut().unwrap().clear_memo();
+            },
+            Some(&"position") => {
+                self.stop();
+                match parse_position(&words[1..]) {
+                    Ok(board) => self.board = board,
+                    Err(e) => send(&format!("info string {}", e))
+                }
+            },
+            Some(&"go") => self.go(&words[1..]),
+            Some(&"stop") => self.stop(),
+            Some(&"quit") => {
+                self.stop();
+                return false;
+            },
+            // Unknown commands are ignored, as the protocol asks
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Plays as a UCI engine until `quit` or the end of input
+pub fn run() {
+    let mut engine = UciEngine::new();
+    read_lines(|line| engine.handle_command(&line));
+    engine.stop();
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_positions() {
+        let board = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"]).unwrap();
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        let args: Vec<&str> = "fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1g1".split(' ').collect();
+        assert_eq!(parse_position(&args).unwrap().to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+
+        assert!(parse_position(&["startpos", "moves", "e2e5"]).is_err());
+        assert!(parse_position(&["fen", "8/8/8", "w"]).is_err());
+        assert!(parse_position(&[]).is_err());
+    }
+
+    #[test]
+    fn parses_go() {
+        let board = Board::new();
+        let params = parse_go(&["depth", "5", "nodes", "1000"], &board);
+        assert_eq!((params.depth, params.max_nodes, params.move_time_ms, params.infinite), (5, 1000, None, false));
+        // Only odd depths are searched
+        assert_eq!(parse_go(&["depth", "4"], &board).depth, 5);
+
+        let params = parse_go(&["wtime", "60000", "btime", "1000", "winc", "1000", "binc", "0"], &board);
+        assert_eq!(params.move_time_ms, Some(60000 / DEFAULT_MOVES_TO_GO + 500));
+
+        let black_board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        let params = parse_go(&["wtime", "60000", "btime", "40", "movestogo", "1"], &black_board);
+        assert_eq!(params.move_time_ms, Some(1));
+
+        let params = parse_go(&["infinite", "movetime", "250", "wtime", "60000"], &board);
+        assert_eq!((params.depth, params.move_time_ms, params.infinite), (MAX_DEPTH, Some(250), true));
+    }
+
+    #[test]
+    fn sets_book_options() {
+        let mut engine = UciEngine::new();
+        engine.handle_command("setoption name BookPolicy value best");
+        engine.handle_command("setoption name BookFile value /no/such/book file.bin");
+        assert_eq!((engine.book_file.as_str(), engine.book_policy), ("/no/such/book file.bin", BookPolicy::Best));
+        // A missing book is reported, and the engine still plays
+        assert!(engine.ai.is_some());
+
+        engine.handle_command("setoption name BookFile value <empty>");
+        assert!(engine.book_file.is_empty());
+    }
+
+    #[test]
+    fn searches_until_stopped() {
+        let mut engine = UciEngine::new();
+        assert!(engine.handle_command("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"));
+        assert!(engine.handle_command("go movetime 100"));
+        assert!(engine.handle_command("isready"));
+        assert!(!engine.handle_command("quit"));
+        assert!(engine.ai.is_some());
+
+        // An infinite search keeps its best move until stopped, even once the depth is reached
+        let mut engine = UciEngine::new();
+        assert!(engine.handle_command("go infinite depth 1"));
+        thread::sleep(Duration::from_millis(200));
+        assert!(!engine.search.as_ref().unwrap().0.is_finished());
+        assert!(engine.handle_command("stop"));
+        assert!(engine.search.is_none() && engine.ai.is_some());
+    }
+}
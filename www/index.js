@@ -26,7 +26,9 @@ class Application {
         this.draggedSqX = 0;
         this.draggedSqY = 0;
 
-        this.main = wasm.Main.new();
+        // Add ?960 to the URL for a random Chess960 start position
+        const isChess960 = new URLSearchParams(window.location.search).has('960');
+        this.main = isChess960 ? wasm.Main.new_chess960(Math.floor(Math.random() * 960)) : wasm.Main.new();
         this.LEN = (0.9 * Math.min(window.innerWidth, window.innerHeight - document.getElementById('title').getBoundingClientRect().height) / 8) >>> 0;
 
         // Pawn = 0, Rook, Knight, Bishop, Queen, King
@@ -83,6 +85,7 @@ class Application {
         }
         this.main.refresh_player_moves();
         this.updateFromWasm();
+        this.showGameStatus();
     }
 
     //////////////////////////////////////////////////
@@ -128,21 +131,16 @@ class Application {
         this.dragged.style.visibility = 'hidden';
 
         const sqCoords = this.getSquareCoordsFromClientCoords(clientX, clientY);
-        if (this.isPlayerWhite) {
-            if (!this.main.try_move(
-                this.draggedSqX,
-                this.draggedSqY,
-                sqCoords.x,
-                sqCoords.y
-            )) return;
-        } else {
-            if (!this.main.try_move(
-                7 - this.draggedSqX,
-                7 - this.draggedSqY,
-                7 - sqCoords.x,
-                7 - sqCoords.y
-            )) return;
+        const move = this.isPlayerWhite ?
+            [this.draggedSqX, this.draggedSqY, sqCoords.x, sqCoords.y] :
+            [7 - this.draggedSqX, 7 - this.draggedSqY, 7 - sqCoords.x, 7 - sqCoords.y];
+
+        let promoteTo = 0;
+        if (this.main.is_promotion_move(...move)) {
+            promoteTo = this.askPromotionPiece();
+            if (promoteTo === 0) return;
         }
+        if (!this.main.try_move(...move, promoteTo)) return;
 
         this.updateFromWasm();
 
@@ -152,11 +150,36 @@ class Application {
             this.main.make_ai_move();
             this.updateFromWasm();
             this.main.refresh_player_moves();
-            this.boardLock = false;
-            console.log('Unlocked board');
+            this.boardLock = this.showGameStatus();
+            console.log(this.boardLock ? 'Game over' : 'Unlocked board');
         }, 250);
     }
 
+    // Returns true if the game is over
+    showGameStatus() {
+        const status = this.main.get_game_status();
+        const text = {
+            [wasm.GameStatusCode.WhiteWins]: 'White wins by checkmate',
+            [wasm.GameStatusCode.BlackWins]: 'Black wins by checkmate',
+            [wasm.GameStatusCode.Stalemate]: 'Draw by stalemate',
+            [wasm.GameStatusCode.DrawByRepetition]: 'Draw by threefold repetition',
+            [wasm.GameStatusCode.DrawByFiftyMove]: 'Draw by fifty-move rule',
+            [wasm.GameStatusCode.DrawByInsufficientMaterial]: 'Draw by insufficient material'
+        }[status];
+        if (text === undefined) return false;
+
+        document.getElementById('title').textContent = text;
+        return true;
+    }
+
+    // Returns a piece number in the same format as `get_piece`, or 0 if cancelled
+    askPromotionPiece() {
+        const answer = window.prompt('Promote to (q, r, b, n)', 'q');
+        if (answer === null) return 0;
+        const index = this.numToLetter.indexOf(answer.trim().toLowerCase());
+        return index > 0 && index < 5 ? index + 1 : 0;
+    }
+
     //////////////////////////////////////////////////
 
     onBoardMouseDown(e) {